@@ -121,13 +121,13 @@ impl Aabb {
     // 调整AABB，使得没有一边比某个delta更窄，如果需要的话进行填充。
     let delta = 0.0001;
     if self.x.size() < delta {
-      self.x.expand(delta);
+      self.x = self.x.expand(delta);
     }
     if self.y.size() < delta {
-      self.y.expand(delta);
+      self.y = self.y.expand(delta);
     }
     if self.z.size() < delta {
-      self.z.expand(delta);
+      self.z = self.z.expand(delta);
     }
   }
 }
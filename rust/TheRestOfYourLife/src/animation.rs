@@ -0,0 +1,165 @@
+use std::ops::{Add, Mul, Sub};
+
+use super::camera::Camera;
+use super::vec3::{Point3, Vec3};
+
+// 相机动画：在关键帧之间用 Catmull-Rom 样条插值相机参数，关键帧的帧号可以不等距，
+// 切线按相邻两个关键帧的差商计算，首尾关键帧取单侧差商，第一帧之前和最后一帧之后保持首尾关键帧的参数。
+// 关键帧文件每行一个关键帧：帧号、lookfrom、lookat、vup 的各三个分量、vfov，最后可选 focus_dist，
+// 省略时对焦到 lookat；# 之后为注释。
+
+#[derive(Clone, Copy)]
+pub struct CameraKey {
+  pub frame: f64,              // Frame number of the key, need not be whole
+  pub lookfrom: Point3,        // Camera origin
+  pub lookat: Point3,          // Point camera is looking at
+  pub vup: Vec3,               // Camera up vector
+  pub vfov: f64,               // Vertical field of view in degrees
+  pub focus_dist: Option<f64>, // Focus distance, None for the distance to lookat
+}
+
+pub struct CameraAnimation {
+  keys: Vec<CameraKey>, // Keyframes sorted by frame number, at least one
+}
+
+impl std::str::FromStr for CameraAnimation {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut keys: Vec<CameraKey> = Vec::new();
+    for (number, line) in s.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or_default().trim();
+      if line.is_empty() {
+        continue;
+      }
+      let values: Vec<f64> = line
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid number on line {}", number + 1))?;
+      let (values, focus_dist) = match values.len() {
+        12 => (&values[..11], Some(values[11])),
+        _ => (&values[..], None),
+      };
+      let [frame, fx, fy, fz, ax, ay, az, ux, uy, uz, vfov] = values[..] else {
+        return Err(format!("expected frame, lookfrom, lookat, vup, vfov and an optional focus distance on line {}", number + 1));
+      };
+      if keys.last().is_some_and(|key| key.frame >= frame) {
+        return Err(format!("frame numbers not increasing on line {}", number + 1));
+      }
+      if vfov <= 0.0 || vfov >= 180.0 || focus_dist.is_some_and(|d| d <= 0.0) {
+        return Err(format!("vfov outside (0, 180) or focus distance not positive on line {}", number + 1));
+      }
+      keys.push(CameraKey {
+        frame,
+        lookfrom: Point3::new(fx, fy, fz),
+        lookat: Point3::new(ax, ay, az),
+        vup: Vec3::new(ux, uy, uz),
+        vfov,
+        focus_dist,
+      });
+    }
+    if keys.is_empty() {
+      return Err(String::from("no keyframes"));
+    }
+    Ok(Self { keys })
+  }
+}
+
+impl CameraAnimation {
+  pub fn first_frame(&self) -> f64 {
+    self.keys[0].frame
+  }
+
+  pub fn last_frame(&self) -> f64 {
+    self.keys[self.keys.len() - 1].frame
+  }
+
+  pub fn apply(&self, frame: f64, cam: &mut Camera) {
+    // 把相机的 lookfrom、lookat、vup、vfov 和 focus_dist 设为 frame 处插值得到的参数。
+    let focus_dist = |key: &CameraKey| key.focus_dist.unwrap_or((key.lookfrom - key.lookat).length());
+    cam.lookfrom = self.interpolate(frame, |key| key.lookfrom);
+    cam.lookat = self.interpolate(frame, |key| key.lookat);
+    cam.vup = self.interpolate(frame, |key| key.vup);
+    cam.vfov = self.interpolate(frame, |key| key.vfov);
+    cam.focus_dist = self.interpolate(frame, focus_dist);
+  }
+
+  fn interpolate<T, F>(&self, frame: f64, value: F) -> T
+  where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+    F: Fn(&CameraKey) -> T,
+  {
+    // 在 frame 所在的区间上做三次 Hermite 插值，切线为对帧号的导数。
+    let keys = &self.keys;
+    let n = keys.len();
+    if n == 1 || frame <= keys[0].frame {
+      return value(&keys[0]);
+    }
+    if frame >= keys[n - 1].frame {
+      return value(&keys[n - 1]);
+    }
+    let i = keys.partition_point(|key| key.frame <= frame) - 1;
+
+    let tangent = |j: usize| {
+      let (a, b) = (j.saturating_sub(1), (j + 1).min(n - 1));
+      (value(&keys[b]) - value(&keys[a])) * (1.0 / (keys[b].frame - keys[a].frame))
+    };
+    let h = keys[i + 1].frame - keys[i].frame;
+    let s = (frame - keys[i].frame) / h;
+    let (s2, s3) = (s * s, s * s * s);
+    value(&keys[i]) * (2.0 * s3 - 3.0 * s2 + 1.0)
+      + tangent(i) * ((s3 - 2.0 * s2 + s) * h)
+      + value(&keys[i + 1]) * (-2.0 * s3 + 3.0 * s2)
+      + tangent(i + 1) * ((s3 - s2) * h)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KEYS: &str = "
+    # frame  lookfrom  lookat  vup  vfov  focus
+    0    0 0 0    0 0 -1    0 1 0    40
+    10   10 0 0   0 0 -1    0 1 0    50   5
+    20   20 0 0   0 0 -1    0 1 0    60
+  ";
+
+  #[test]
+  fn interpolation_passes_through_keys_and_holds_outside() {
+    let animation: CameraAnimation = KEYS.parse().unwrap();
+    assert_eq!((animation.first_frame(), animation.last_frame()), (0.0, 20.0));
+    for (frame, vfov) in [(-5.0, 40.0), (0.0, 40.0), (10.0, 50.0), (20.0, 60.0), (25.0, 60.0)] {
+      assert!((animation.interpolate(frame, |key| key.vfov) - vfov).abs() < 1e-12);
+    }
+    // 等距且线性变化的关键帧插值后仍是线性的。
+    for frame in [2.5, 7.0, 13.0, 19.5] {
+      assert!((animation.interpolate(frame, |key| key.lookfrom.x()) - frame).abs() < 1e-12);
+    }
+  }
+
+  #[test]
+  fn apply_defaults_focus_to_lookat() {
+    let animation: CameraAnimation = KEYS.parse().unwrap();
+    let mut cam = Camera::default();
+    animation.apply(0.0, &mut cam);
+    assert!((cam.focus_dist - 1.0).abs() < 1e-12);
+    animation.apply(10.0, &mut cam);
+    assert!((cam.focus_dist - 5.0).abs() < 1e-12);
+  }
+
+  #[test]
+  fn rejects_invalid_keyframes() {
+    let error = |s: &str| s.parse::<CameraAnimation>().err().unwrap();
+    assert_eq!(error("# nothing\n"), "no keyframes");
+    assert_eq!(error("0 0 0 0 0 0 -1 0 1 0 x"), "invalid number on line 1");
+    assert_eq!(
+      error("0 0 0 0 0 0 -1 0 1 0"),
+      "expected frame, lookfrom, lookat, vup, vfov and an optional focus distance on line 1",
+    );
+    assert_eq!(error("1 0 0 0 0 0 -1 0 1 0 40\n1 0 0 0 0 0 -1 0 1 0 40"), "frame numbers not increasing on line 2");
+    assert_eq!(error("0 0 0 0 0 0 -1 0 1 0 180"), "vfov outside (0, 180) or focus distance not positive on line 1");
+    assert_eq!(error("0 0 0 0 0 0 -1 0 1 0 40 0"), "vfov outside (0, 180) or focus distance not positive on line 1");
+  }
+}
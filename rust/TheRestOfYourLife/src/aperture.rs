@@ -0,0 +1,185 @@
+use std::rc::Rc;
+
+use super::rtweekend;
+use super::rtw_stb_image::RtwImage;
+use super::camera_model::concentric_disk;
+
+// 光圈形状：把 [0, 1)^2 上的透镜样本映射为透镜上的点，光圈内接于单位圆，图像光圈拉伸到 [-1, 1]^2。
+// 每种形状都按面积均匀采样（图像光圈按透过率采样），样本权重恒为一，因此形状只改变焦外光斑，不改变画面亮度。
+// 猫眼渐晕：离轴的像点看到的光圈有一部分被镜筒挡住，近似为光圈与一个朝画面中心偏移的单位圆的交集，
+// 偏移量与像点到画面中心的距离成正比，落在交集外的样本被挡住。
+
+pub const APERTURES: [&str; 4] = ["disk", "polygon", "annulus", "image"];
+
+#[derive(Clone)]
+pub enum ApertureShape {
+  Disk,                                     // Round aperture of a lens with many blades
+  Polygon { blades: usize, rotation: f64 }, // Regular polygon with a vertex rotation degrees counterclockwise from +x
+  Annulus { inner_radius: f64 },            // Ring of a mirror lens, inner_radius the fraction of the aperture blocked
+  Image(Rc<ApertureImage>),                 // Grayscale transmission image, white fully open
+}
+
+pub struct ApertureImage {
+  width: usize,
+  height: usize,
+  cdf: Vec<f64>, // Running sum of the pixel transmissions in row-major order
+}
+
+impl ApertureImage {
+  pub fn new(image: &RtwImage) -> Option<Self> {
+    // 以像素亮度为透过率建立累积分布，图像为空或全黑时返回 None。
+    let (width, height) = (image.width(), image.height());
+    let mut cdf = Vec::with_capacity(width * height);
+    let mut sum = 0.0;
+    for y in 0..height {
+      for x in 0..width {
+        let p = image.pixel_data(x, y);
+        sum += (0.2126 * p[0] as f64 + 0.7152 * p[1] as f64 + 0.0722 * p[2] as f64) / 255.0;
+        cdf.push(sum);
+      }
+    }
+    (sum > 0.0).then_some(Self { width, height, cdf })
+  }
+
+  fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+    // 按累积分布选出像素，u.0 的剩余部分和 u.1 决定像素内的位置。
+    let total = self.cdf[self.cdf.len() - 1];
+    let target = u.0 * total;
+    let k = self.cdf.partition_point(|&c| c <= target).min(self.cdf.len() - 1);
+    let start = if k > 0 { self.cdf[k - 1] } else { 0.0 };
+    let jitter = ((target - start) / (self.cdf[k] - start)).clamp(0.0, 1.0);
+
+    let x = (k % self.width) as f64 + jitter;
+    let y = (k / self.width) as f64 + u.1;
+    (2.0 * x / self.width as f64 - 1.0, 1.0 - 2.0 * y / self.height as f64)
+  }
+}
+
+#[derive(Clone)]
+pub struct Aperture {
+  pub shape: ApertureShape,
+  pub vignetting: f64, // Offset of the clipping circle at the image corners, zero for no cat's-eye vignetting
+}
+
+impl Default for Aperture {
+  fn default() -> Self {
+    Self {
+      shape: ApertureShape::Disk,
+      vignetting: 0.0,
+    }
+  }
+}
+
+impl Aperture {
+  pub fn sample(&self, lens: (f64, f64), film_offset: (f64, f64)) -> Option<(f64, f64)> {
+    // 返回光圈上的点，film_offset 是像点相对画面中心的位置，在画面角落长度为一。被镜筒挡住时返回 None。
+    let p = match &self.shape {
+      ApertureShape::Disk => concentric_disk(lens),
+      ApertureShape::Polygon { blades, rotation } => {
+        // 把多边形分成 blades 个以中心为顶点的三角形，u.0 选择三角形，其剩余部分和 u.1 在三角形内均匀采样。
+        let n = *blades as f64;
+        let k = ((lens.0 * n) as usize).min(blades - 1);
+        let a = (lens.0 * n - k as f64).sqrt();
+        let b = lens.1;
+        let angle = |k: usize| rtweekend::degrees_to_radians(*rotation) + 2.0 * rtweekend::PI * k as f64 / n;
+        let (sin0, cos0) = angle(k).sin_cos();
+        let (sin1, cos1) = angle(k + 1).sin_cos();
+        (a * ((1.0 - b) * cos0 + b * cos1), a * ((1.0 - b) * sin0 + b * sin1))
+      },
+      ApertureShape::Annulus { inner_radius } => {
+        let r = (inner_radius * inner_radius + lens.0 * (1.0 - inner_radius * inner_radius)).sqrt();
+        let (sin_theta, cos_theta) = (2.0 * rtweekend::PI * lens.1).sin_cos();
+        (r * cos_theta, r * sin_theta)
+      },
+      ApertureShape::Image(image) => image.sample(lens),
+    };
+
+    if self.vignetting > 0.0 {
+      let (cx, cy) = (-self.vignetting * film_offset.0, -self.vignetting * film_offset.1);
+      if (p.0 - cx) * (p.0 - cx) + (p.1 - cy) * (p.1 - cy) > 1.0 {
+        return None;
+      }
+    }
+    Some(p)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn samples(shape: ApertureShape) -> Vec<(f64, f64)> {
+    // 在 64 x 64 的分层网格上取透镜样本。
+    let aperture = Aperture { shape, vignetting: 0.0 };
+    let n = 64;
+    (0..n * n)
+      .map(|k| {
+        let lens = (((k % n) as f64 + 0.5) / n as f64, ((k / n) as f64 + 0.5) / n as f64);
+        aperture.sample(lens, (0.0, 0.0)).unwrap()
+      })
+      .collect()
+  }
+
+  fn fraction(samples: &[(f64, f64)], inside: impl Fn(&(f64, f64)) -> bool) -> f64 {
+    samples.iter().filter(|p| inside(p)).count() as f64 / samples.len() as f64
+  }
+
+  fn assert_uniform_quadrants(samples: &[(f64, f64)], center: (f64, f64)) {
+    for (sx, sy) in [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)] {
+      let share = fraction(samples, |p| (p.0 - center.0) * sx > 0.0 && (p.1 - center.1) * sy > 0.0);
+      assert!((share - 0.25).abs() < 0.01, "quadrant share {}", share);
+    }
+  }
+
+  #[test]
+  fn polygon_samples_fill_the_polygon_uniformly() {
+    let blades = 6;
+    let samples = samples(ApertureShape::Polygon { blades, rotation: 0.0 });
+
+    // 点到各条边法线方向上的最大投影，边心距为 cos(pi / blades)。
+    let apothem = (rtweekend::PI / blades as f64).cos();
+    let extent = |p: &(f64, f64)| {
+      (0..blades)
+        .map(|k| {
+          let (sin, cos) = (rtweekend::PI * (2 * k + 1) as f64 / blades as f64).sin_cos();
+          p.0 * cos + p.1 * sin
+        })
+        .fold(f64::NEG_INFINITY, f64::max)
+    };
+    assert!(samples.iter().all(|p| extent(p) <= apothem + 1e-9));
+
+    // 缩小一半的多边形面积为四分之一。
+    let share = fraction(&samples, |p| extent(p) < 0.5 * apothem);
+    assert!((share - 0.25).abs() < 0.01, "inner polygon share {}", share);
+    assert_uniform_quadrants(&samples, (0.0, 0.0));
+  }
+
+  #[test]
+  fn annulus_samples_fill_the_ring_uniformly() {
+    let inner_radius = 0.5;
+    let samples = samples(ApertureShape::Annulus { inner_radius });
+    let radius = |p: &(f64, f64)| (p.0 * p.0 + p.1 * p.1).sqrt();
+    assert!(samples.iter().all(|p| radius(p) >= inner_radius - 1e-9 && radius(p) <= 1.0 + 1e-9));
+
+    // 半径 sqrt((inner^2 + 1) / 2) 的圆把圆环分成面积相等的两半。
+    let median = ((inner_radius * inner_radius + 1.0) / 2.0).sqrt();
+    let share = fraction(&samples, |p| radius(p) < median);
+    assert!((share - 0.5).abs() < 0.01, "inner ring share {}", share);
+    assert_uniform_quadrants(&samples, (0.0, 0.0));
+  }
+
+  #[test]
+  fn image_samples_fill_the_open_pixels_uniformly() {
+    // 4 x 4 的图像只有左上角 2 x 2 个像素透光，对应光圈上的 [-1, 0] x [0, 1]。
+    let transmission = |k: usize| if k % 4 < 2 && k / 4 < 2 { 1.0 } else { 0.0 };
+    let cdf = (0..16).scan(0.0, |sum, k| {
+      *sum += transmission(k);
+      Some(*sum)
+    });
+    let image = ApertureImage { width: 4, height: 4, cdf: cdf.collect() };
+    let samples = samples(ApertureShape::Image(Rc::new(image)));
+
+    assert!(samples.iter().all(|p| (-1.0..=0.0).contains(&p.0) && (0.0..=1.0).contains(&p.1)));
+    assert_uniform_quadrants(&samples, (-0.5, 0.5));
+  }
+}
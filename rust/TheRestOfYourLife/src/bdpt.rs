@@ -0,0 +1,575 @@
+use super::rtweekend;
+use super::color::Color;
+use super::camera::Camera;
+use super::hittable::{HitRecord, Hittable};
+use super::integrator::Integrator;
+use super::framebuffer::Framebuffer;
+use super::interval::Interval;
+use super::material::ScatterRecord;
+use super::onb::Onb;
+use super::ray::Ray;
+use super::vec3::{self, Point3, Vec3};
+use super::progress;
+use super::stats;
+
+// 双向路径追踪：分别从相机和光源出发生成子路径，再以所有可能的方式连接它们，
+// 并用平衡启发式的多重重要性采样（MIS）权重组合各个策略。
+// 注意：ConstantMedium 的散射点按表面顶点处理，其任意法线会使涉及体积散射的权重有偏差。
+pub struct Bdpt;
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+  Camera,
+  Light,
+  Surface,
+}
+
+struct Vertex {
+  kind: VertexKind,            // Whether the vertex lies on the camera, a light or a scene surface
+  p: Point3,                   // Position of the vertex
+  n: Vec3,                     // Surface normal, the zero vector for camera vertices
+  wo: Vec3,                    // Unit direction towards the previous vertex
+  rec: HitRecord,              // Hit record of the surface the vertex lies on
+  srec: Option<ScatterRecord>, // Scattering at the vertex, None if the material does not scatter
+  beta: Color,                 // Throughput from the start of the subpath to the vertex
+  delta: bool,                 // Whether the vertex is specular and cannot be connected to
+  pdf_fwd: f64,                // Area density of generating the vertex along the subpath
+  pdf_rev: f64,                // Area density of generating the vertex in the opposite direction
+}
+
+impl Vertex {
+  fn camera(p: Point3, beta: Color) -> Self {
+    Self {
+      kind: VertexKind::Camera,
+      p,
+      n: Vec3::default(),
+      wo: Vec3::default(),
+      rec: HitRecord::default(),
+      srec: None,
+      beta,
+      delta: false,
+      pdf_fwd: 0.0,
+      pdf_rev: 0.0,
+    }
+  }
+
+  fn light(rec: HitRecord, beta: Color, pdf: f64) -> Self {
+    Self {
+      kind: VertexKind::Light,
+      p: rec.p,
+      n: rec.normal,
+      wo: Vec3::default(),
+      rec,
+      srec: None,
+      beta,
+      delta: false,
+      pdf_fwd: pdf,
+      pdf_rev: 0.0,
+    }
+  }
+
+  fn surface(rec: HitRecord, wo: Vec3, beta: Color) -> Self {
+    Self {
+      kind: VertexKind::Surface,
+      p: rec.p,
+      n: rec.normal,
+      wo,
+      rec,
+      srec: None,
+      beta,
+      delta: false,
+      pdf_fwd: 0.0,
+      pdf_rev: 0.0,
+    }
+  }
+
+  fn on_surface(&self) -> bool {
+    self.kind != VertexKind::Camera
+  }
+
+  fn is_connectible(&self) -> bool {
+    match self.kind {
+      VertexKind::Camera | VertexKind::Light => true,
+      VertexKind::Surface => !self.delta && self.srec.is_some(),
+    }
+  }
+
+  fn outward_normal(&self) -> Vec3 {
+    if self.rec.front_face { self.rec.normal } else { -self.rec.normal }
+  }
+
+  fn f_cos(&self, next: &Vertex) -> Color {
+    // 返回朝 next 方向散射时的 BSDF 与本顶点余弦项之积。
+    let (Some(srec), Some(mat)) = (&self.srec, &self.rec.mat) else {
+      return Color::default();
+    };
+    let r_in = Ray::new(self.p + self.wo, -self.wo);
+    let scattered = Ray::new(self.p, next.p - self.p);
+    srec.attenuation * mat.scattering_pdf(&r_in, &self.rec, &scattered)
+  }
+
+  fn le(&self, w: Vec3) -> Color {
+    // 返回本顶点朝方向 w 发出的辐射亮度。
+    let Some(mat) = &self.rec.mat else {
+      return Color::default();
+    };
+    let outward_normal = self.outward_normal();
+    let mut rec = self.rec.clone();
+    rec.front_face = vec3::dot(outward_normal, w) > 0.0;
+    rec.normal = if rec.front_face { outward_normal } else { -outward_normal };
+    let r_in = Ray::new(self.p + w, -w);
+    mat.emitted(&r_in, &rec, rec.u, rec.v, rec.p)
+  }
+
+  fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+    // 将从本顶点出发的立体角密度转换为 next 处的面积密度。
+    let w = next.p - self.p;
+    let dist_squared = w.length_squared();
+    if dist_squared == 0.0 {
+      return 0.0;
+    }
+    let mut pdf = pdf / dist_squared;
+    if next.on_surface() {
+      pdf *= vec3::dot(next.n, w / dist_squared.sqrt()).abs();
+    }
+    pdf
+  }
+
+  fn pdf(&self, cam: &Camera, next: &Vertex) -> f64 {
+    // 返回由本顶点采样得到 next 的面积密度。
+    if self.kind == VertexKind::Light {
+      return self.pdf_light(next);
+    }
+
+    let w = next.p - self.p;
+    if w.length_squared() == 0.0 {
+      return 0.0;
+    }
+    let pdf = match self.kind {
+      VertexKind::Camera => cam.pdf_we(&Ray::new(self.p, w)).1,
+      _ => match &self.srec {
+        Some(srec) => srec.pdf.value(w),
+        None => 0.0,
+      },
+    };
+    self.convert_density(pdf, next)
+  }
+
+  fn pdf_light(&self, next: &Vertex) -> f64 {
+    // 返回本顶点所在的发光表面按余弦分布发射光线并到达 next 的面积密度。
+    let w = next.p - self.p;
+    if w.length_squared() == 0.0 {
+      return 0.0;
+    }
+    let pdf_dir = vec3::dot(self.outward_normal(), vec3::unit_vector(w)).max(0.0) / rtweekend::PI;
+    self.convert_density(pdf_dir, next)
+  }
+
+  fn pdf_light_origin(&self, lights: &dyn Hittable, time: f64) -> f64 {
+    lights.surface_pdf_value(self.p, time)
+  }
+}
+
+impl Bdpt {
+  fn random_walk(world: &dyn Hittable, mut ray: Ray, mut beta: Color, pdf: f64, max_vertices: usize, path: &mut Vec<Vertex>) -> Color {
+    // 从 path 的最后一个顶点出发延伸子路径，如果光线逃逸出场景，则返回逃逸时的吞吐量。
+    let mut pdf_fwd = pdf;
+    let mut added = 0;
+
+    loop {
+      let mut rec = HitRecord::default();
+      stats::count(|c| c.rays += 1);
+      if !world.hit(&ray, &Interval::new(0.001, rtweekend::INFINITY), &mut rec) {
+        return beta;
+      }
+      let Some(mat) = rec.mat.clone() else {
+        return Color::default();
+      };
+
+      let mut srec = ScatterRecord::default();
+      let scatters = mat.scatter(&ray, &rec, &mut srec);
+
+      let mut vertex = Vertex::surface(rec, -vec3::unit_vector(ray.direction()), beta);
+      vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf_fwd, &vertex);
+      added += 1;
+
+      if !scatters {
+        path.push(vertex);
+        return Color::default();
+      }
+
+      if srec.skip_pdf {
+        // 镜面顶点无法连接，正向和反向密度都记为零。
+        vertex.delta = true;
+        beta = beta * srec.attenuation;
+        let scattered = std::mem::take(&mut srec.skip_pdf_ray);
+        vertex.srec = Some(srec);
+        path.push(vertex);
+        let n = path.len();
+        path[n - 2].pdf_rev = 0.0;
+        if added >= max_vertices {
+          return Color::default();
+        }
+        pdf_fwd = 0.0;
+        ray = scattered;
+        continue;
+      }
+
+      let scattered = Ray::new_with_time(vertex.p, srec.pdf.generate(), ray.time());
+      let pdf_dir = srec.pdf.value(scattered.direction());
+      let f_cos = srec.attenuation * mat.scattering_pdf(&ray, &vertex.rec, &scattered);
+      let pdf_rev = srec.pdf.value(-ray.direction());
+      vertex.srec = Some(srec);
+      path.push(vertex);
+
+      let n = path.len();
+      path[n - 2].pdf_rev = path[n - 1].convert_density(pdf_rev, &path[n - 2]);
+
+      if added >= max_vertices || pdf_dir <= 0.0 {
+        return Color::default();
+      }
+
+      beta = beta * f_cos / pdf_dir;
+      pdf_fwd = pdf_dir;
+      ray = scattered;
+    }
+  }
+
+  fn generate_camera_subpath(cam: &Camera, r: Ray, world: &dyn Hittable, path: &mut Vec<Vertex>) -> Color {
+    let (_, pdf_dir) = cam.pdf_we(&r);
+    path.push(Vertex::camera(r.origin(), Color::new(1.0, 1.0, 1.0)));
+    Self::random_walk(world, r, Color::new(1.0, 1.0, 1.0), pdf_dir, cam.max_depth + 1, path)
+  }
+
+  fn generate_light_subpath(cam: &Camera, time: f64, world: &dyn Hittable, lights: &dyn Hittable, path: &mut Vec<Vertex>) {
+    let mut rec = HitRecord::default();
+    let pdf_pos = lights.random_on_surface(&mut rec, time);
+    if pdf_pos <= 0.0 {
+      return;
+    }
+
+    // 在光源表面的法线半球内按余弦分布采样发射方向。
+    let uvw = Onb::new_from_w(rec.normal);
+    let direction = uvw.local_v(vec3::random_cosine_direction());
+    let cosine = vec3::dot(vec3::unit_vector(direction), rec.normal);
+    let pdf_dir = cosine / rtweekend::PI;
+    if pdf_dir <= 0.0 {
+      return;
+    }
+
+    let mut vertex = Vertex::light(rec, Color::default(), pdf_pos);
+    let le = vertex.le(direction);
+    if le.near_zero() {
+      return;
+    }
+    vertex.beta = le / pdf_pos;
+
+    let ray = Ray::new_with_time(vertex.p, direction, time);
+    let beta = le * cosine / (pdf_pos * pdf_dir);
+    path.push(vertex);
+    Self::random_walk(world, ray, beta, pdf_dir, cam.max_depth, path);
+  }
+
+  fn unoccluded(world: &dyn Hittable, a: Point3, b: Point3, time: f64) -> bool {
+    let d = b - a;
+    let dist = d.length();
+    let mut rec = HitRecord::default();
+    stats::count(|c| {
+      c.rays += 1;
+      c.shadow_rays += 1;
+    });
+    !world.hit(&Ray::new_with_time(a, d / dist, time), &Interval::new(0.001, dist - 0.001), &mut rec)
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn connect(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+    time: f64,
+    film: &mut Framebuffer,
+  ) -> Color {
+    // 连接光源子路径的前 s 个顶点与相机子路径的前 t 个顶点，返回对当前像素的贡献。
+    // t == 1 时贡献落在其他像素上，直接累加到 film 中。
+    if s == 0 {
+      let pt = &camera_path[t - 1];
+      if pt.kind != VertexKind::Surface {
+        return Color::default();
+      }
+      let l = pt.le(pt.wo) * pt.beta;
+      if l.near_zero() {
+        return l;
+      }
+      return l * Self::mis_weight(cam, lights, light_path, camera_path, None, s, t, time);
+    }
+
+    if t == 1 {
+      let qs = &light_path[s - 1];
+      if !qs.is_connectible() {
+        return Color::default();
+      }
+      let Some(sample) = cam.sample_wi(qs.p) else {
+        return Color::default();
+      };
+      if sample.pdf <= 0.0 || sample.we <= 0.0 {
+        return Color::default();
+      }
+
+      let sampled = Vertex::camera(sample.lens_point, Color::new(1.0, 1.0, 1.0) * (sample.we / sample.pdf));
+      let l = qs.beta * qs.f_cos(&sampled) * sampled.beta;
+      if l.near_zero() || !Self::unoccluded(world, qs.p, sampled.p, time) {
+        return Color::default();
+      }
+
+      let weight = Self::mis_weight(cam, lights, light_path, camera_path, Some(&sampled), s, t, time);
+      film.splat(sample.raster.0, sample.raster.1, l * weight);
+      return Color::default();
+    }
+
+    if s == 1 {
+      let pt = &camera_path[t - 1];
+      if !pt.is_connectible() {
+        return Color::default();
+      }
+
+      let mut rec = HitRecord::default();
+      let pdf_area = lights.random_on_surface(&mut rec, time);
+      if pdf_area <= 0.0 {
+        return Color::default();
+      }
+      let mut sampled = Vertex::light(rec, Color::default(), pdf_area);
+
+      let wi = sampled.p - pt.p;
+      let dist_squared = wi.length_squared();
+      let cos_light = vec3::dot(sampled.n, vec3::unit_vector(wi)).abs();
+      if dist_squared == 0.0 || cos_light == 0.0 {
+        return Color::default();
+      }
+      let pdf = pdf_area * dist_squared / cos_light;
+      sampled.beta = sampled.le(-wi) / pdf;
+      sampled.pdf_fwd = sampled.pdf_light_origin(lights, time);
+
+      let l = pt.beta * pt.f_cos(&sampled) * sampled.beta;
+      if l.near_zero() || !Self::unoccluded(world, pt.p, sampled.p, time) {
+        return Color::default();
+      }
+      return l * Self::mis_weight(cam, lights, light_path, camera_path, Some(&sampled), s, t, time);
+    }
+
+    let qs = &light_path[s - 1];
+    let pt = &camera_path[t - 1];
+    if !qs.is_connectible() || !pt.is_connectible() {
+      return Color::default();
+    }
+
+    let dist_squared = (pt.p - qs.p).length_squared();
+    if dist_squared == 0.0 {
+      return Color::default();
+    }
+    let l = qs.beta * qs.f_cos(pt) * pt.f_cos(qs) * pt.beta / dist_squared;
+    if l.near_zero() || !Self::unoccluded(world, qs.p, pt.p, time) {
+      return Color::default();
+    }
+    l * Self::mis_weight(cam, lights, light_path, camera_path, None, s, t, time)
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn mis_weight(
+    cam: &Camera,
+    lights: &dyn Hittable,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    sampled: Option<&Vertex>,
+    s: usize,
+    t: usize,
+    time: f64,
+  ) -> f64 {
+    if s + t == 2 {
+      return 1.0;
+    }
+
+    // 复制连接后路径上各顶点的密度，并用采样得到的顶点替换对应端点。
+    let mut cam_fwd: Vec<f64> = camera_path[..t].iter().map(|v| v.pdf_fwd).collect();
+    let mut cam_rev: Vec<f64> = camera_path[..t].iter().map(|v| v.pdf_rev).collect();
+    let mut cam_delta: Vec<bool> = camera_path[..t].iter().map(|v| v.delta).collect();
+    let mut light_fwd = vec![0.0; s];
+    let mut light_rev = vec![0.0; s];
+    let mut light_delta = vec![false; s];
+    for (i, v) in light_path.iter().take(s).enumerate() {
+      light_fwd[i] = v.pdf_fwd;
+      light_rev[i] = v.pdf_rev;
+      light_delta[i] = v.delta;
+    }
+
+    let mut qs = if s > 1 { Some(&light_path[s - 1]) } else { None };
+    let mut pt = &camera_path[t - 1];
+    if let Some(v) = sampled {
+      if s == 1 {
+        qs = Some(v);
+        light_fwd[0] = v.pdf_fwd;
+        light_rev[0] = v.pdf_rev;
+        light_delta[0] = false;
+      } else {
+        pt = v;
+        cam_fwd[0] = v.pdf_fwd;
+        cam_rev[0] = v.pdf_rev;
+        cam_delta[0] = false;
+      }
+    }
+    let qs_minus = if s > 1 { Some(&light_path[s - 2]) } else { None };
+    let pt_minus = if t > 1 { Some(&camera_path[t - 2]) } else { None };
+
+    // 更新连接端点及其前驱的反向密度。
+    cam_rev[t - 1] = match qs {
+      Some(qs) => qs.pdf(cam, pt),
+      None => pt.pdf_light_origin(lights, time),
+    };
+    cam_delta[t - 1] = false;
+    if let Some(pt_minus) = pt_minus {
+      cam_rev[t - 2] = match qs {
+        Some(_) => pt.pdf(cam, pt_minus),
+        None => pt.pdf_light(pt_minus),
+      };
+    }
+    if let Some(qs) = qs {
+      light_rev[s - 1] = pt.pdf(cam, qs);
+      light_delta[s - 1] = false;
+      if let Some(qs_minus) = qs_minus {
+        light_rev[s - 2] = qs.pdf(cam, qs_minus);
+      }
+    }
+
+    let remap0 = |f: f64| if f != 0.0 { f } else { 1.0 };
+
+    // 依次计算其他策略生成同一路径的密度比值。
+    let mut sum_ri = 0.0;
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+      ri *= remap0(cam_rev[i]) / remap0(cam_fwd[i]);
+      if !cam_delta[i] && !cam_delta[i - 1] {
+        sum_ri += ri;
+      }
+    }
+
+    ri = 1.0;
+    for i in (0..s).rev() {
+      ri *= remap0(light_rev[i]) / remap0(light_fwd[i]);
+      let delta_lightvertex = i > 0 && light_delta[i - 1];
+      if !light_delta[i] && !delta_lightvertex {
+        sum_ri += ri;
+      }
+    }
+
+    1.0 / (1.0 + sum_ri)
+  }
+}
+
+impl Integrator for Bdpt {
+  fn render(&self, cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable, film: &mut Framebuffer) {
+    let sqrt_spp = cam.sqrt_spp();
+    let max_depth = cam.max_depth as i32;
+    let mut camera_path = Vec::with_capacity(cam.max_depth + 2);
+    let mut light_path = Vec::with_capacity(cam.max_depth + 1);
+
+    progress::start("scanlines", cam.film_height(), 0);
+    for j in 0..cam.film_height() {
+      for i in 0..cam.film_width() {
+        for s_j in 0..sqrt_spp {
+          for s_i in 0..sqrt_spp {
+            let sample = cam.get_ray_sample(i as i32, j as i32, s_i as i32, s_j as i32);
+            let r = sample.ray;
+            let time = r.time();
+
+            camera_path.clear();
+            light_path.clear();
+            let escaped = Self::generate_camera_subpath(cam, r, world, &mut camera_path);
+            Self::generate_light_subpath(cam, time, world, lights, &mut light_path);
+
+            // 即使光源子路径为空（例如采样到了不发光的重要性采样目标），s == 1 策略也会重新采样光源。
+            let mut pixel_color = escaped * cam.background;
+            for t in 1..=camera_path.len() {
+              for s in 0..=light_path.len().max(1) {
+                let depth = t as i32 + s as i32 - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > max_depth {
+                  continue;
+                }
+                pixel_color += Self::connect(cam, world, lights, &light_path, &camera_path, s, t, time, film);
+              }
+            }
+            film.add_filtered(i, j, sample.position, sample.weight * pixel_color, None);
+          }
+        }
+      }
+      progress::advance(j + 1);
+    }
+    progress::finish();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::hittable_list::HittableList;
+  use crate::material::{DiffuseLight, Lambertian};
+  use crate::quad::Quad;
+  use crate::rtweekend::Pcg32;
+  use crate::sphere::Sphere;
+
+  fn mean(film: &Framebuffer, samples_per_pixel: usize) -> f64 {
+    let mut sum = 0.0;
+    for j in 0..film.height() {
+      for i in 0..film.width() {
+        let c = film.pixel(i, j);
+        sum += (c.x() + c.y() + c.z()) / 3.0;
+      }
+    }
+    sum / (samples_per_pixel * film.width() * film.height()) as f64
+  }
+
+  #[test]
+  fn converges_to_the_path_traced_mean() {
+    // 只有漫反射表面的盒子里，双向路径追踪与路径追踪的平均亮度应当一致。
+    let white = Rc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let red = Rc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let light: Rc<dyn Hittable> = Rc::new(Quad::new(
+      Point3::new(3.0, 9.99, 3.0),
+      Vec3::new(4.0, 0.0, 0.0),
+      Vec3::new(0.0, 0.0, 4.0),
+      Rc::new(DiffuseLight::new_with_color(Color::new(4.0, 4.0, 4.0))),
+    ));
+    let mut world = HittableList::default();
+    world.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 10.0), white.clone())));
+    world.add(Rc::new(Quad::new(Point3::new(0.0, 10.0, 0.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 10.0), white.clone())));
+    world.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, 10.0), Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 10.0, 0.0), white.clone())));
+    world.add(Rc::new(Quad::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 10.0, 0.0), Vec3::new(0.0, 0.0, 10.0), red)));
+    world.add(Rc::new(Quad::new(Point3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 10.0, 0.0), Vec3::new(0.0, 0.0, 10.0), white.clone())));
+    world.add(Rc::new(Sphere::new(Point3::new(4.0, 2.0, 6.0), 2.0, white)));
+    world.add(Rc::clone(&light));
+    let mut lights = HittableList::default();
+    lights.add(light);
+
+    let mut cam = Camera::default();
+    cam.image_width = 12;
+    cam.samples_per_pixel = 64;
+    // 两种积分器截断路径长度的方式相差一次反弹，取足够大的深度使截断的能量可以忽略。
+    cam.max_depth = 30;
+    cam.vfov = 60.0;
+    cam.lookfrom = Point3::new(5.0, 5.0, -3.0);
+    cam.lookat = Point3::new(5.0, 5.0, 0.0);
+
+    let previous = rtweekend::set_random_source(Some(Rc::new(RefCell::new(Pcg32::new(11, 0)))));
+    let path = mean(&cam.render(&world, &lights), cam.samples_per_pixel);
+    let bdpt = mean(&cam.render_with(&Bdpt, &world, &lights), cam.samples_per_pixel);
+    rtweekend::set_random_source(previous);
+
+    assert!(path > 0.0);
+    assert!((bdpt - path).abs() < 0.03 * path, "bdpt mean {} differs from path traced mean {}", bdpt, path);
+  }
+}
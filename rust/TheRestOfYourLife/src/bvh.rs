@@ -8,6 +8,7 @@ use super::hittable_list::HittableList;
 use super::ray::Ray;
 use super::interval::Interval;
 use super::aabb::{self, Aabb};
+use super::stats;
 
 pub struct BvhNode {
   left: Rc<dyn Hittable>,
@@ -93,6 +94,8 @@ impl BvhNode {
 
 impl Hittable for BvhNode {
   fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+    stats::count(|c| c.bvh_node_visits += 1);
+
     let mut ray_t = ray_t.clone();
     if !self.bbox.hit(r, &mut ray_t) {
       return false;
@@ -1,4 +1,7 @@
-use super::rtweekend;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::rtweekend::{self, Pcg32};
 use super::color::Color;
 use super::hittable::{HitRecord, Hittable};
 use super::ray::Ray;
@@ -10,31 +13,123 @@ use super::pdf::{
   MixturePdf,
 };
 use super::material::ScatterRecord;
+use super::integrator::Integrator;
+use super::framebuffer::{Aov, Framebuffer};
+use super::progressive::{RenderState, WorkerShare};
+use super::progress;
+use super::filter::Filter;
+use super::camera_model::{CameraModel, Projection, Stereo, TiltShift};
+use super::lens_system::LensSystem;
+use super::aperture::Aperture;
+use super::exposure::{Exposure, Shutter};
+use super::stats;
 
 pub struct Camera {
-  pub aspect_ratio: f64,  // Ratio of image width over height
-  pub image_width: usize, // Rendered image width in pixel count
-  pub samples_per_pixel: usize, // Count of random samples for each pixel
-  pub max_depth: usize,   // Maximum number of ray bounces into scene
-  pub background: Color,  // Background color for rays that miss
-  pub vfov: f64,          // Vertical field of view in degrees
-  pub lookfrom: Point3,   // Camera origin
-  pub lookat: Point3,     // Point camera is looking at
-  pub vup: Vec3,          // Camera up vector
-  pub defocus_angle: f64, // Defocus blur angle
-  pub focus_dist: f64,    // Focus distance
-  image_height: usize,    // Rendered image height
-  sqrt_spp: usize,        // Square root of samples per pixel
-  recip_sqrt_spp: f64,    // Reciprocal of square root of samples per pixel
-  center: Point3,         // Camera center
-  pixel00_loc: Point3,    // Location of pixel 0, 0
-  pixel_delta_u: Vec3,    // Offset to pixel to the right
-  pixel_delta_v: Vec3,    // Offset to pixel below
-  u: Vec3,                // Camera horizontal axis
-  v: Vec3,                // Camera vertical axis
-  w: Vec3,                // Camera forward axis
-  defocus_disk_u: Vec3,   // Defocus disk horizontal axis
-  defocus_disk_v: Vec3,   // Defocus disk vertical axis
+  pub aspect_ratio: f64,                  // Ratio of image width over height
+  pub image_width: usize,                 // Rendered image width in pixel count
+  pub samples_per_pixel: usize,           // Count of random samples for each pixel
+  pub max_depth: usize,                   // Maximum number of ray bounces into scene
+  pub background: Color,                  // Background color for rays that miss
+  pub vfov: f64,                          // Vertical field of view in degrees, used by the perspective projection
+  pub lookfrom: Point3,                   // Camera origin
+  pub lookat: Point3,                     // Point camera is looking at
+  pub vup: Vec3,                          // Camera up vector
+  pub defocus_angle: f64,                 // Defocus blur angle
+  pub focus_dist: f64,                    // Focus distance
+  pub projection: Projection,             // How film positions map to rays leaving the camera
+  pub model: Option<Rc<dyn CameraModel>>, // Camera model generating the rays, None to build one from projection
+  pub aperture: Aperture,                 // Shape of the thin lens opening defocused highlights take
+  pub tilt_shift: TiltShift,              // Viewport shift and plane of focus tilt of the perspective projection
+  pub lens: Option<LensSystem>,           // Lens prescription traced instead of the projection, it must be able to focus at focus_dist
+  pub stereo: Option<Stereo>,             // Stereo rig rendering both eyes into one image, None for a single view
+  pub exposure: Option<Exposure>,         // Physical exposure overriding vfov and defocus_angle, None for unit brightness
+  pub shutter: Shutter,                   // When each row of the image is exposed, giving the times of camera rays
+  pub adaptive: Option<AdaptiveSampling>, // Adaptive sampling settings, None for samples_per_pixel everywhere
+  pub russian_roulette: Option<usize>,    // Bounces after which paths may end by Russian roulette, None to never
+  pub crop: Option<CropWindow>,           // Pixels of the image to render, None for all of them
+  pub log_bounces: bool,                  // Log every bounce of every path to stderr, meant for single pixels
+  pub filter: Filter,                     // Pixel reconstruction filter samples are weighted with
+  pub indirect_clamp: Option<f64>,        // Largest luminance of a sample's indirect light, None to never clamp
+  pub regularize: Option<f64>,            // Roughness given to specular vertices after a diffuse bounce, None to keep them
+  image_height: usize,                    // Rendered image height
+  film_x: usize,                          // Image column of the first rendered pixel
+  film_y: usize,                          // Image row of the first rendered pixel
+  film_width: usize,                      // Count of rendered columns
+  film_height: usize,                     // Count of rendered rows
+  bounce_log: RefCell<BounceLog>,         // Sample and throughput of the path being logged
+  sqrt_spp: usize,                        // Square root of samples per pixel
+  recip_sqrt_spp: f64,                    // Reciprocal of square root of samples per pixel
+  center: Point3,                         // Camera center
+  pixel00_loc: Point3,                    // Location of pixel 0, 0
+  pixel_delta_u: Vec3,                    // Offset to pixel to the right
+  pixel_delta_v: Vec3,                    // Offset to pixel below
+  u: Vec3,                                // Camera horizontal axis
+  v: Vec3,                                // Camera vertical axis
+  w: Vec3,                                // Camera forward axis
+  defocus_disk_u: Vec3,                   // Defocus disk horizontal axis
+  defocus_disk_v: Vec3,                   // Defocus disk vertical axis
+  film_area: f64,                         // Viewport area of the crop window at unit distance from the camera
+  lens_area: f64,                         // Defocus disk area, one for a pinhole camera
+  brightness: f64,                        // Factor on the weight of every camera ray
+  active_model: Rc<dyn CameraModel>,      // Camera model rays are generated with
+}
+
+#[derive(Clone, Copy)]
+pub struct AdaptiveSampling {
+  pub min_samples_per_pixel: usize, // Samples every pixel takes before its error is checked, also added per pass
+  pub max_samples_per_pixel: usize, // Upper bound on the samples a pixel can take
+  pub target_error: f64,            // Relative error below which a pixel stops taking samples
+}
+
+#[derive(Clone, Copy)]
+pub struct CropWindow {
+  pub x0: usize, // First column of the window
+  pub y0: usize, // First row of the window
+  pub x1: usize, // Column after the last column of the window
+  pub y1: usize, // Row after the last row of the window
+}
+
+impl CropWindow {
+  pub fn pixel(i: usize, j: usize) -> Self {
+    // Returns the window covering only pixel i,j.
+    Self { x0: i, y0: j, x1: i + 1, y1: j + 1 }
+  }
+}
+
+impl std::str::FromStr for CropWindow {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    // 解析 "x0,y0,x1,y1" 形式的字符串，窗口包含 x0..x1 列和 y0..y1 行。
+    let values: Vec<usize> = s.split(',').map(str::parse).collect::<Result<_, _>>().map_err(|_| "invalid crop bound")?;
+    let [x0, y0, x1, y1] = values[..] else {
+      return Err(String::from("expected x0,y0,x1,y1"));
+    };
+    if x0 >= x1 || y0 >= y1 {
+      return Err(String::from("empty crop window"));
+    }
+    Ok(Self { x0, y0, x1, y1 })
+  }
+}
+
+#[derive(Default)]
+struct BounceLog {
+  sample: usize,     // Index of the sample being traced
+  throughput: Color, // Product of the path weights up to the current bounce
+}
+
+pub struct CameraSample {
+  pub ray: Ray,              // Ray leaving the camera
+  pub position: (f64, f64), // Continuous pixel coordinates the ray passes through
+  pub weight: f64,           // Factor on the light carried along the ray, zero outside a fisheye image circle
+}
+
+pub struct ImportanceSample {
+  pub wi: Vec3,           // Unit direction from the reference point to the lens
+  pub pdf: f64,           // Solid angle density of wi
+  pub lens_point: Point3, // Sampled point on the lens
+  pub raster: (f64, f64), // Continuous pixel coordinates the sample lands on
+  pub we: f64,            // Importance carried back along wi
 }
 
 impl Default for Camera {
@@ -51,7 +146,27 @@ impl Default for Camera {
       vup: Vec3::new(0.0, 1.0, 0.0),
       defocus_angle: 0.0,
       focus_dist: 10.0,
+      projection: Projection::Perspective,
+      model: None,
+      aperture: Aperture::default(),
+      tilt_shift: TiltShift::default(),
+      lens: None,
+      stereo: None,
+      exposure: None,
+      shutter: Shutter::default(),
+      adaptive: None,
+      russian_roulette: None,
+      crop: None,
+      log_bounces: false,
+      filter: Filter::default(),
+      indirect_clamp: None,
+      regularize: None,
       image_height: 0,
+      film_x: 0,
+      film_y: 0,
+      film_width: 0,
+      film_height: 0,
+      bounce_log: RefCell::new(BounceLog::default()),
       sqrt_spp: 10.0_f64.sqrt() as usize,
       recip_sqrt_spp: 1.0 / (10.0_f64.sqrt()),
       center: Point3::default(),
@@ -63,32 +178,233 @@ impl Default for Camera {
       w: Vec3::default(),
       defocus_disk_u: Vec3::default(),
       defocus_disk_v: Vec3::default(),
+      film_area: 1.0,
+      lens_area: 1.0,
+      brightness: 1.0,
+      active_model: Projection::Perspective.model(1.0, 90.0, 10.0, 0.0, &Aperture::default(), TiltShift::default()),
     }
   }
 }
 
 impl Camera {
-  pub fn render(&mut self, world: &dyn Hittable, lights: &dyn Hittable) {
+  pub fn render(&mut self, world: &dyn Hittable, lights: &dyn Hittable) -> Framebuffer {
     self.initialize();
 
-    println!("P3\n{} {}\n255", self.image_width, self.image_height);
-    let stdout = std::io::stdout();
-
-    for j in 0..self.image_height {
-      eprintln!("\rScanlines remaining: {}", self.image_height - j);
-      for i in 0..self.image_width {
-        let mut pixel_color = Color::default();
-        for s_j in 0..self.sqrt_spp {
-          for s_i in 0..self.sqrt_spp {
-            let r = self.get_ray(i as i32, j as i32, s_i as i32, s_j as i32);
-            pixel_color += self.ray_color(&r, self.max_depth, world, lights);
+    let mut film = Framebuffer::new_with_aovs(self.film_width, self.film_height);
+    film.enable_filter(self.filter);
+
+    if let Some(adaptive) = &self.adaptive {
+      self.render_adaptive(adaptive, world, lights, &mut film);
+    } else {
+      progress::start("scanlines", self.film_height, 0);
+      for j in 0..self.film_height {
+        for i in 0..self.film_width {
+          for s_j in 0..self.sqrt_spp {
+            for s_i in 0..self.sqrt_spp {
+              let sample = self.get_ray_sample(i as i32, j as i32, s_i as i32, s_j as i32);
+              self.add_sample(&sample, i, j, world, lights, &mut film);
+            }
           }
         }
-        pixel_color.write_color(&mut stdout.lock(), self.samples_per_pixel).unwrap();
+        progress::advance(j + 1);
       }
+      progress::finish();
     }
 
-    eprintln!("\nDone.");
+    film.resolve_filter(self.samples_per_pixel);
+    film
+  }
+
+  fn render_adaptive(
+    &self,
+    adaptive: &AdaptiveSampling,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    film: &mut Framebuffer,
+  ) {
+    // 每个像素的样本按奇偶分成两半，分别累加的两个半缓冲区之差用于估计像素的相对误差。
+    // 每一遍为每个未收敛的像素追加 min_samples_per_pixel 个样本，直到误差低于目标或达到最大样本数。
+    let width = self.film_width;
+    let height = self.film_height;
+    let batch = adaptive.min_samples_per_pixel.max(2);
+    let strata = (batch as f64).sqrt() as usize;
+
+    film.enable_sample_counts();
+    let mut odd_half = vec![Color::default(); width * height];
+    let mut active: Vec<usize> = (0..width * height).collect();
+
+    progress::start("pixels converged", width * height, 0);
+    while !active.is_empty() {
+
+      for &p in active.iter() {
+        let (i, j) = (p % width, p / width);
+        let taken = film.sample_count(i, j, 0);
+        let count = batch.min(adaptive.max_samples_per_pixel - taken);
+
+        for k in 0..count {
+          // 在 strata x strata 的网格上分层，超出网格的样本在整个像素内均匀采样。
+          let (x, y) = if k < strata * strata {
+            (
+              i as f64 + ((k % strata) as f64 + rtweekend::random_double()) / strata as f64,
+              j as f64 + ((k / strata) as f64 + rtweekend::random_double()) / strata as f64,
+            )
+          } else {
+            (i as f64 + rtweekend::random_double(), j as f64 + rtweekend::random_double())
+          };
+          let sample = self.get_ray_sample_at(x, y);
+          let color = self.add_sample(&sample, i, j, world, lights, film);
+          if (taken + k) % 2 == 1 {
+            odd_half[p] += color;
+          }
+        }
+        film.add_sample_count(i, j, count);
+      }
+
+      active.retain(|&p| {
+        let (i, j) = (p % width, p / width);
+        let n = film.sample_count(i, j, 0);
+        if n >= adaptive.max_samples_per_pixel {
+          return false;
+        }
+
+        let n_odd = n / 2;
+        let sum = film.pixel(i, j);
+        let odd = odd_half[p] / n_odd as f64;
+        let even = (sum - odd_half[p]) / (n - n_odd) as f64;
+        let mean = sum / n as f64;
+
+        let difference = (even - odd).x().abs() + (even - odd).y().abs() + (even - odd).z().abs();
+        let error = difference / (mean.x() + mean.y() + mean.z()).max(1e-3);
+        error > adaptive.target_error
+      });
+      progress::advance(width * height - active.len());
+    }
+    progress::finish();
+  }
+
+  fn add_sample(
+    &self,
+    sample: &CameraSample,
+    i: usize,
+    j: usize,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    film: &mut Framebuffer,
+  ) -> Color {
+    // 追踪像素 i,j 的一条相机光线，将其颜色和 AOV 经过滤波器累加到帧缓冲中，并返回颜色。
+    let r = &sample.ray;
+    if self.log_bounces {
+      let mut log = self.bounce_log.borrow_mut();
+      eprintln!("Sample {} of pixel {},{}: ray from {} towards {}", log.sample, self.film_x + i, self.film_y + j, r.origin(), r.direction());
+      log.sample += 1;
+      log.throughput = Color::new(1.0, 1.0, 1.0);
+    }
+
+    // 权重为零的样本（例如鱼眼成像圆外）不追踪，但仍计入滤波器权重，使这些像素保持黑色。
+    let mut aov = Aov::default();
+    if sample.weight == 0.0 {
+      film.add_filtered(i, j, sample.position, Color::default(), Some(&aov));
+      return Color::default();
+    }
+    let (direct, indirect) = self.trace(r, self.max_depth, 0, 0, world, lights, Some(&mut aov));
+    let (direct, indirect) = (sample.weight * direct, sample.weight * self.clamp_indirect(indirect));
+    aov.direct = direct;
+    aov.indirect = indirect;
+
+    if self.log_bounces {
+      eprintln!("  radiance {} (direct {}, indirect {})", direct + indirect, direct, indirect);
+    }
+
+    film.add_filtered(i, j, sample.position, direct + indirect, Some(&aov));
+    direct + indirect
+  }
+
+  pub fn render_progressive<F: FnMut(&mut RenderState) -> bool>(
+    &mut self,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    state: &mut RenderState,
+    share: &WorkerShare,
+    mut on_pass: F,
+  ) -> Result<(), String> {
+    // 第 k 遍为每个像素在第 k 个分层格子中取一个样本，共 passes() 遍。只渲染 share 所分到的图块。
+    // 每遍结束后调用 on_pass，调用者可以在其中保存检查点，或者重新开始渲染；on_pass 返回 false 时停止渲染。
+    self.initialize();
+
+    if state.film.width() != self.film_width || state.film.height() != self.film_height {
+      if state.passes > 0 {
+        return Err(format!(
+          "The render state is {}x{} but the image is {}x{}.",
+          state.film.width(), state.film.height(), self.film_width, self.film_height,
+        ));
+      }
+      state.restart(self.film_width, self.film_height);
+    }
+
+    let passes = self.passes();
+    let pixels = (self.film_width * self.film_height) as u64;
+    let rng = Rc::new(RefCell::new(Pcg32::new(state.seed, 0)));
+    let previous = rtweekend::set_random_source(Some(rng.clone()));
+
+    progress::start("passes", passes, state.passes);
+    while state.passes < passes {
+      let s_i = (state.passes % self.sqrt_spp) as i32;
+      let s_j = (state.passes / self.sqrt_spp) as i32;
+
+      for j in 0..self.film_height {
+        for i in 0..self.film_width {
+          if !share.owns(i, j, self.film_width) {
+            continue;
+          }
+          let pixel = (j * self.film_width + i) as u64;
+          *rng.borrow_mut() = Pcg32::new(state.seed, state.passes as u64 * pixels + pixel);
+
+          let sample = self.get_ray_sample(i as i32, j as i32, s_i, s_j);
+          self.add_sample(&sample, i, j, world, lights, &mut state.film);
+          state.film.add_sample_count(i, j, 1);
+        }
+      }
+
+      state.passes += 1;
+      progress::advance(state.passes);
+      if !on_pass(state) {
+        break;
+      }
+    }
+
+    rtweekend::set_random_source(previous);
+    progress::finish();
+    Ok(())
+  }
+
+  pub fn render_with(&mut self, integrator: &dyn Integrator, world: &dyn Hittable, lights: &dyn Hittable) -> Framebuffer {
+    self.initialize();
+
+    let mut film = Framebuffer::new(self.film_width, self.film_height);
+    film.enable_filter(self.filter);
+    integrator.render(self, world, lights, &mut film);
+    film.resolve_filter(self.samples_per_pixel);
+
+    film
+  }
+
+  pub fn film_width(&self) -> usize {
+    // Returns the count of columns rendered, the crop window's width if there is one.
+    self.film_width
+  }
+
+  pub fn film_height(&self) -> usize {
+    self.film_height
+  }
+
+  pub fn sqrt_spp(&self) -> usize {
+    self.sqrt_spp
+  }
+
+  pub fn passes(&self) -> usize {
+    // Returns the count of passes a progressive render takes, one stratified sample per pixel each.
+    let sqrt_spp = (self.samples_per_pixel as f64).sqrt() as usize;
+    sqrt_spp * sqrt_spp
   }
 
   fn initialize(&mut self) {
@@ -99,11 +415,40 @@ impl Camera {
 
     self.center = self.lookfrom;
 
-    // 确定视口尺寸。
-    let theta = rtweekend::degrees_to_radians(self.vfov);
-    let h = (theta / 2.0).tan();
-    let viewport_height = 2.0 * h * self.focus_dist;
-    let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
+    // 立体渲染时每只眼睛只占图像的一半，单眼模型按半幅图像的宽高比创建。
+    let aspect = self.image_width as f64 / self.image_height as f64;
+    let eye_aspect = self.stereo.map_or(aspect, |stereo| stereo.eye_aspect(aspect));
+
+    // 物理曝光由传感器和光圈推出视场角与失焦角，并与快门一起决定亮度。
+    if let Some(exposure) = self.exposure {
+      self.vfov = exposure.vfov(eye_aspect);
+      self.defocus_angle = exposure.defocus_angle(self.focus_dist);
+    }
+    self.brightness = self.exposure.map_or(1.0, |exposure| exposure.brightness(&self.shutter));
+
+    // 确定视口尺寸。正交投影的视口高度直接给定，与视场角和对焦距离无关。
+    let viewport_height = match self.projection {
+      Projection::Perspective => {
+        let theta = rtweekend::degrees_to_radians(self.vfov);
+        let h = (theta / 2.0).tan();
+        2.0 * h * self.focus_dist
+      },
+      Projection::Orthographic { view_height } => view_height,
+      // 全景投影不使用视口，这里只需给出一个有效的尺寸。
+      _ => 2.0 * self.focus_dist,
+    };
+    let viewport_width = viewport_height * aspect;
+    let eye_model = match (&self.model, &self.lens) {
+      (Some(model), _) => Rc::clone(model),
+      (None, Some(lens)) => lens.model(eye_aspect, self.focus_dist).expect("lens system cannot focus at focus_dist"),
+      (None, None) => {
+        self.projection.model(eye_aspect, self.vfov, self.focus_dist, self.defocus_angle, &self.aperture, self.tilt_shift)
+      },
+    };
+    self.active_model = match self.stereo {
+      Some(stereo) => stereo.rig(eye_model),
+      None => eye_model,
+    };
 
     // 计算相机坐标系的 u,v,w 单位基向量。
     self.w = vec3::unit_vector(self.lookfrom - self.lookat);
@@ -118,71 +463,292 @@ impl Camera {
     self.pixel_delta_u = viewport_u / self.image_width as f64;
     self.pixel_delta_v = viewport_v / self.image_height as f64;
 
-    // 计算左上角像素的位置。
+    // 计算左上角像素的位置，移轴时视口随之平移。
     let viewport_upper_left = self.center
       - (self.focus_dist * self.w)
-      - (0.5 * viewport_u)
-      - (0.5 * viewport_v);
+      - ((0.5 - self.tilt_shift.shift.0) * viewport_u)
+      - ((0.5 + self.tilt_shift.shift.1) * viewport_v);
     self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
+    // 只渲染裁剪窗口内的像素：胶片只覆盖窗口，像素 0,0 移到窗口的左上角，因此积分器无需感知裁剪。
+    let (width, height) = (self.image_width, self.image_height);
+    let crop = self.crop.unwrap_or(CropWindow { x0: 0, y0: 0, x1: width, y1: height });
+    self.film_x = crop.x0.min(width);
+    self.film_y = crop.y0.min(height);
+    self.film_width = crop.x1.min(width).saturating_sub(self.film_x);
+    self.film_height = crop.y1.min(height).saturating_sub(self.film_y);
+    self.pixel00_loc += self.film_x as f64 * self.pixel_delta_u + self.film_y as f64 * self.pixel_delta_v;
+
     // 计算相机失焦盘的基向量。
     let defocus_radius = self.focus_dist * (rtweekend::degrees_to_radians(self.defocus_angle / 2.0)).tan();
     self.defocus_disk_u = self.u * defocus_radius;
     self.defocus_disk_v = self.v * defocus_radius;
-  }
 
-  fn get_ray(&self, i: i32, j: i32, s_i: i32, s_j: i32) -> Ray {
-    // Get a randomly sampled camera ray for the pixel at location i,j.
-    let pixel_center = self.pixel00_loc + i as f64 * self.pixel_delta_u + j as f64 * self.pixel_delta_v;
-    let pixel_sample = pixel_center + self.pixel_sample_square(s_i, s_j);
-
-    let ray_origin = if self.defocus_angle <= 0.0 {
-      self.center
+    // 计算重要性函数所需的单位距离视口面积和透镜面积。相机光线只穿过裁剪窗口，
+    // 光源子路径也只按窗口内的像素数发射，因此重要性按窗口覆盖的那部分视口归一化。
+    let crop_fraction = (self.film_width * self.film_height) as f64 / (self.image_width * self.image_height) as f64;
+    self.film_area = crop_fraction * viewport_width * viewport_height / (self.focus_dist * self.focus_dist);
+    self.lens_area = if self.defocus_angle <= 0.0 {
+      1.0
     } else {
-      self.defocus_disk_sample()
+      rtweekend::PI * defocus_radius * defocus_radius
     };
-    let ray_direction = pixel_sample - ray_origin;
-    let ray_time = rtweekend::random_double();
+  }
+
+  pub fn get_ray_sample(&self, i: i32, j: i32, s_i: i32, s_j: i32) -> CameraSample {
+    // Get a randomly sampled camera ray for the pixel at location i,j, with the pixel coordinates it passes through.
+    let (px, py) = self.pixel_sample_square(s_i, s_j);
+    self.get_ray_sample_at(i as f64 + 0.5 + px, j as f64 + 0.5 + py)
+  }
 
-    Ray::new_with_time(ray_origin, ray_direction, ray_time)
+  pub fn get_ray_sample_at(&self, x: f64, y: f64) -> CameraSample {
+    // Get a camera ray through the continuous pixel coordinates x,y, with its weight.
+    let film = (
+      (self.film_x as f64 + x) / self.image_width as f64,
+      (self.film_y as f64 + y) / self.image_height as f64,
+    );
+    let lens = (rtweekend::random_double(), rtweekend::random_double());
+    let time = self.shutter.sample(film.1, rtweekend::random_double());
+    let camera_ray = self.active_model.generate_ray(film, lens, time);
+
+    stats::count(|c| c.camera_rays += 1);
+    CameraSample {
+      ray: self.camera_to_world(&camera_ray.ray),
+      position: (x, y),
+      weight: self.brightness * camera_ray.weight,
+    }
   }
 
-  fn pixel_sample_square(&self, s_i: i32, s_j: i32) -> Vec3 {
-    // Returns a random point in the square surrounding a pixel at the origin.
+  pub fn sample_time(&self) -> f64 {
+    // Returns a random time while any row of the image is exposed.
+    self.shutter.sample_frame(rtweekend::random_double())
+  }
+
+  fn camera_to_world(&self, r: &Ray) -> Ray {
+    // 相机空间的 x、y、z 轴分别对应 u、v、w 基向量，原点对应相机中心。
+    let to_world = |p: Vec3| p.x() * self.u + p.y() * self.v + p.z() * self.w;
+    Ray::new_with_time(self.center + to_world(r.origin()), to_world(r.direction()), r.time())
+  }
+
+  fn pixel_sample_square(&self, s_i: i32, s_j: i32) -> (f64, f64) {
+    // Returns a random offset in pixels in the square surrounding a pixel at the origin.
     let px = -0.5 + self.recip_sqrt_spp * (s_i as f64 + rtweekend::random_double());
     let py = -0.5 + self.recip_sqrt_spp * (s_j as f64 + rtweekend::random_double());
-    px * self.pixel_delta_u + py * self.pixel_delta_v
+    (px, py)
   }
 
-  fn defocus_disk_sample(&self) -> Point3 {
-    // Returns a random point in the defocus disk.
+  fn defocus_disk_sample(&self, lens_center: Point3) -> Point3 {
+    // Returns a random point in the defocus disk around lens_center.
     let p = vec3::random_in_unit_disk();
-    self.center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v
+    lens_center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v
+  }
+
+  // 以下供 BDPT 使用的重要性函数只适用于透视投影的薄透镜，不经过相机模型。
+
+  pub fn raster_position(&self, r: &Ray) -> Option<(f64, f64)> {
+    // Returns the continuous pixel coordinates that a ray leaving the lens passes through.
+    let cos_theta = vec3::dot(r.direction(), -self.w);
+    if cos_theta <= 0.0 {
+      return None;
+    }
+
+    let focus_point = r.at(self.focus_dist / cos_theta);
+    let viewport_upper_left = self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+    let offset = focus_point - viewport_upper_left;
+    let x = vec3::dot(offset, self.pixel_delta_u) / self.pixel_delta_u.length_squared();
+    let y = vec3::dot(offset, self.pixel_delta_v) / self.pixel_delta_v.length_squared();
+
+    if x < 0.0 || x >= self.film_width as f64 || y < 0.0 || y >= self.film_height as f64 {
+      return None;
+    }
+    Some((x, y))
   }
 
-  fn ray_color(&self, r: &Ray, depth: usize, world: &dyn Hittable, lights: &dyn Hittable) -> Color {
+  pub fn we(&self, r: &Ray) -> f64 {
+    // Returns the importance emitted by the camera along a ray leaving the lens.
+    if self.raster_position(r).is_none() {
+      return 0.0;
+    }
+
+    let cos_theta = vec3::dot(vec3::unit_vector(r.direction()), -self.w);
+    let cos2_theta = cos_theta * cos_theta;
+    self.brightness / (self.film_area * self.lens_area * cos2_theta * cos2_theta)
+  }
+
+  pub fn pdf_we(&self, r: &Ray) -> (f64, f64) {
+    // Returns the positional and directional densities with which get_ray generates a ray.
+    if self.raster_position(r).is_none() {
+      return (0.0, 0.0);
+    }
+
+    let cos_theta = vec3::dot(vec3::unit_vector(r.direction()), -self.w);
+    (1.0 / self.lens_area, 1.0 / (self.film_area * cos_theta * cos_theta * cos_theta))
+  }
+
+  pub fn sample_wi(&self, p: Point3) -> Option<ImportanceSample> {
+    // Samples a point on the lens as seen from p, returning None if it lands outside the image.
+    let lens_point = if self.defocus_angle <= 0.0 {
+      self.center
+    } else {
+      self.defocus_disk_sample(self.center)
+    };
+
+    let to_lens = lens_point - p;
+    let dist = to_lens.length();
+    if dist == 0.0 {
+      return None;
+    }
+    let wi = to_lens / dist;
+
+    let r = Ray::new(lens_point, -wi);
+    let raster = self.raster_position(&r)?;
+
+    let cos_lens = vec3::dot(wi, self.w).abs();
+    Some(ImportanceSample {
+      wi,
+      pdf: dist * dist / (cos_lens * self.lens_area),
+      lens_point,
+      raster,
+      we: self.we(&r),
+    })
+  }
+
+  pub fn ray_color(&self, r: &Ray, depth: usize, world: &dyn Hittable, lights: &dyn Hittable) -> Color {
+    let (direct, indirect) = self.trace(r, depth, 0, 0, world, lights, None);
+    direct + self.clamp_indirect(indirect)
+  }
+
+  fn clamp_indirect(&self, indirect: Color) -> Color {
+    // 将一个样本的间接光照按比例缩小到亮度不超过 indirect_clamp，以有偏的方式抑制萤火虫噪点。
+    let Some(max_luminance) = self.indirect_clamp else {
+      return indirect;
+    };
+    let luminance = indirect.luminance();
+    if luminance <= max_luminance {
+      return indirect;
+    }
+    stats::count(|c| c.clamped_samples += 1);
+    (max_luminance / luminance) * indirect
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn trace(
+    &self,
+    r: &Ray,
+    depth: usize,
+    bounce: usize,
+    diffuse_bounces: usize,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    mut aov: Option<&mut Aov>,
+  ) -> (Color, Color) {
+    // 与 ray_color 相同的路径追踪，但将结果拆分为直接光照和间接光照，
+    // 并在 aov 不为空时记录第一个交点及第一个非镜面交点的表面属性。diffuse_bounces 为路径上已有的非镜面反弹次数。
     let mut rec = HitRecord::default();
 
+    // 在相机直接看到或经过一次非镜面反弹后到达相机的光属于直接光照。镜面反弹不计入，
+    // 因此透过玻璃或在镜子中看到的光源及其直接照亮的表面仍属于直接光照。
+    let split = |c: Color| if diffuse_bounces <= 1 { (c, Color::default()) } else { (Color::default(), c) };
+    let scale = |f: Color, (direct, indirect): (Color, Color)| (f * direct, f * indirect);
+
     // 如果我们超过了光线反弹限制，就不再收集光线。
     if depth == 0 {
-      return Color::default();
+      if self.log_bounces {
+        self.log_bounce(bounce, "reached the maximum depth, path ends", Color::default());
+      }
+      stats::count(|c| c.record_path(bounce));
+      return (Color::default(), Color::default());
     }
 
     // 如果光线没有击中了世界中的任何东西，则返回背景颜色。
+    stats::count(|c| c.rays += 1);
     if !world.hit(r, &Interval::new(0.001, rtweekend::INFINITY), &mut rec) {
-      return self.background;
+      if self.log_bounces {
+        self.log_bounce(bounce, &format!("missed, background {}, path ends", self.background), Color::default());
+      }
+      stats::count(|c| c.record_path(bounce));
+      return split(self.background);
+    }
+
+    if bounce == 0 {
+      if let Some(aov) = aov.as_deref_mut() {
+        aov.depth = rec.t * r.direction().length();
+        aov.object_id = rec.object_id;
+        aov.material_id = rec.material_id;
+      }
     }
 
     if let Some(mat) = rec.mat.clone() {
       let mut srec = ScatterRecord::default();
       let color_from_emission = mat.emitted(r, &rec, rec.u, rec.v, rec.p);
 
+      if self.log_bounces {
+        let message = format!(
+          "hit object {} material {} at t={} p={} normal={} front_face={} emitted {}",
+          rec.object_id, rec.material_id, rec.t, rec.p, rec.normal, rec.front_face, color_from_emission,
+        );
+        self.log_bounce(bounce, &message, Color::new(1.0, 1.0, 1.0));
+      }
+
       if !mat.scatter(r, &rec, &mut srec) {
-        return color_from_emission;
+        // 光源的反照率取其自发光颜色并截断到 [0,1]。
+        if let Some(aov) = aov {
+          let c = color_from_emission;
+          aov.albedo = Color::new(c.x().min(1.0), c.y().min(1.0), c.z().min(1.0));
+          aov.normal = rec.normal;
+        }
+        if self.log_bounces {
+          self.log_bounce(bounce, "absorbed, path ends", Color::default());
+        }
+        stats::count(|c| c.record_path(bounce));
+        return split(color_from_emission);
+      }
+
+      // 俄罗斯轮盘赌：反弹足够多次之后，以衰减的最大分量为概率继续路径，并用该概率加权以保持无偏。
+      let mut attenuation = srec.attenuation;
+      if self.russian_roulette.is_some_and(|min_bounces| bounce >= min_bounces) {
+        let survival = attenuation.x().max(attenuation.y()).max(attenuation.z()).min(0.95);
+        if rtweekend::random_double() >= survival {
+          if self.log_bounces {
+            let message = format!("ended by Russian roulette with survival probability {}", survival);
+            self.log_bounce(bounce, &message, Color::default());
+          }
+          stats::count(|c| {
+            c.russian_roulette_kills += 1;
+            c.record_path(bounce);
+          });
+          return split(color_from_emission);
+        }
+        attenuation /= survival;
       }
+      stats::count(|c| c.bounces += 1);
 
       if srec.skip_pdf {
-        return srec.attenuation * self.ray_color(&srec.skip_pdf_ray, depth - 1, world, lights);
+        // 镜面表面没有有意义的反照率和法线，继续沿镜面路径寻找。
+        let mut specular_ray = srec.skip_pdf_ray;
+        if let (Some(roughness), true) = (self.regularize, diffuse_bounces > 0) {
+          // 路径正则化：漫反射之后的镜面方向在半径为 roughness 的球内随机扰动，镜面反弹变成一个有宽度的波瓣，
+          // 经镜面到达的小光源因此更容易被随机击中，焦散的噪声以模糊为代价降低。这里不在该顶点上采样光源，
+          // 也不修正扰动带来的能量变化，因此结果有偏。扰动后穿过表面另一侧的方向被舍弃，保留原方向。
+          let direction = vec3::unit_vector(specular_ray.direction()) + roughness * vec3::random_unit_vector();
+          let side = vec3::dot(specular_ray.direction(), rec.normal);
+          if vec3::dot(direction, rec.normal) * side > 0.0 {
+            specular_ray = Ray::new_with_time(specular_ray.origin(), direction, specular_ray.time());
+            stats::count(|c| c.regularized_vertices += 1);
+          }
+        }
+
+        if self.log_bounces {
+          let message = format!("specular scatter towards {}, weight {}", specular_ray.direction(), attenuation);
+          self.log_bounce(bounce, &message, attenuation);
+        }
+        let sample = self.trace(&specular_ray, depth - 1, bounce + 1, diffuse_bounces, world, lights, aov);
+        return scale(attenuation, sample);
+      }
+
+      if let Some(aov) = aov {
+        aov.albedo = srec.attenuation;
+        aov.normal = rec.normal;
       }
 
       let light_pdf = HittablePdf::new(lights, rec.p);
@@ -193,12 +759,131 @@ impl Camera {
 
       let scattering_pdf = mat.scattering_pdf(r, &rec, &scattered);
 
-      let sample_color = self.ray_color(&scattered, depth - 1, world, lights);
-      let color_from_scatter = (srec.attenuation * scattering_pdf * sample_color) / pdf;
+      if self.log_bounces {
+        let weight = attenuation * scattering_pdf / pdf;
+        let message = format!(
+          "scattered towards {}, light pdf {}, material pdf {}, mixture pdf {}, scattering pdf {}, weight {}",
+          scattered.direction(), light_pdf.value(scattered.direction()), srec.pdf.value(scattered.direction()),
+          pdf, scattering_pdf, weight,
+        );
+        self.log_bounce(bounce, &message, weight);
+      }
+
+      let sample = self.trace(&scattered, depth - 1, bounce + 1, diffuse_bounces + 1, world, lights, None);
+      let (direct, indirect) = scale(attenuation * scattering_pdf / pdf, sample);
 
-      color_from_emission + color_from_scatter
+      let (emission_direct, emission_indirect) = split(color_from_emission);
+      (emission_direct + direct, emission_indirect + indirect)
     } else {
-      Color::default()
+      if self.log_bounces {
+        self.log_bounce(bounce, "hit an object without a material, path ends", Color::default());
+      }
+      stats::count(|c| c.record_path(bounce));
+      (Color::default(), Color::default())
+    }
+  }
+
+  fn log_bounce(&self, bounce: usize, message: &str, weight: Color) {
+    // 输出一次反弹的记录及到达此处的路径通量，然后把 weight 乘到通量上。
+    let mut log = self.bounce_log.borrow_mut();
+    eprintln!("  bounce {}: {} (throughput {})", bounce, message, log.throughput);
+    log.throughput = log.throughput * weight;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::hittable_list::HittableList;
+  use crate::material::{DiffuseLight, Lambertian, Metal};
+  use crate::quad::Quad;
+
+  #[test]
+  fn light_seen_in_a_mirror_is_direct() {
+    // 相机经镜子看到身后的光源，镜面反弹不计入直接与间接光照的划分。
+    let light: Rc<dyn Hittable> = Rc::new(Quad::new(
+      Point3::new(-10.0, -10.0, -5.0),
+      Vec3::new(20.0, 0.0, 0.0),
+      Vec3::new(0.0, 20.0, 0.0),
+      Rc::new(DiffuseLight::new_with_color(Color::new(1.0, 1.0, 1.0))),
+    ));
+    let mut world = HittableList::default();
+    world.add(Rc::new(Quad::new(
+      Point3::new(-10.0, -10.0, 0.0),
+      Vec3::new(0.0, 20.0, 0.0),
+      Vec3::new(20.0, 0.0, 0.0),
+      Rc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0)),
+    )));
+    world.add(Rc::clone(&light));
+    let mut lights = HittableList::default();
+    lights.add(light);
+
+    let mut cam = Camera {
+      image_width: 4,
+      samples_per_pixel: 4,
+      lookfrom: Point3::new(0.0, 0.0, -4.0),
+      ..Default::default()
+    };
+    let film = cam.render(&world, &lights);
+
+    for j in 0..film.height() {
+      for i in 0..film.width() {
+        let aov = film.aov(i, j);
+        assert!(aov.direct.x() > 0.0);
+        assert_eq!(aov.indirect.length(), 0.0);
+      }
     }
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn adaptive_sampling_stops_early_only_where_converged() {
+    // 相机朝 +z 看，图像左半幅是 +x 一侧被光源照亮的漫反射面，噪声使其继续采样；右半幅只看到恒定的黑色背景，首遍之后即停止。
+    let light: Rc<dyn Hittable> = Rc::new(Quad::new(
+      Point3::new(-1.0, -1.0, -6.0),
+      Vec3::new(2.0, 0.0, 0.0),
+      Vec3::new(0.0, 2.0, 0.0),
+      Rc::new(DiffuseLight::new_with_color(Color::new(8.0, 8.0, 8.0))),
+    ));
+    let mut world = HittableList::default();
+    world.add(Rc::new(Quad::new(
+      Point3::new(0.0, -10.0, 0.0),
+      Vec3::new(0.0, 20.0, 0.0),
+      Vec3::new(10.0, 0.0, 0.0),
+      Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+    world.add(Rc::clone(&light));
+    let mut lights = HittableList::default();
+    lights.add(light);
+
+    let adaptive = AdaptiveSampling {
+      min_samples_per_pixel: 8,
+      max_samples_per_pixel: 64,
+      target_error: 0.001,
+    };
+    let mut cam = Camera {
+      image_width: 8,
+      aspect_ratio: 1.0,
+      lookfrom: Point3::new(0.0, 0.0, -4.0),
+      adaptive: Some(adaptive),
+      ..Default::default()
+    };
+    let previous = rtweekend::set_random_source(Some(Rc::new(RefCell::new(Pcg32::new(7, 0)))));
+    let film = cam.render(&world, &lights);
+    rtweekend::set_random_source(previous);
+
+    // 噪声像素的奇偶两半偶尔会恰好吻合而提前停止，因此只要求其平均样本数远高于最小值。
+    let mut noisy_samples = 0;
+    for j in 0..film.height() {
+      for i in 0..film.width() {
+        let count = film.sample_count(i, j, 0);
+        if i < film.width() / 2 {
+          noisy_samples += count;
+        } else {
+          assert_eq!(count, adaptive.min_samples_per_pixel);
+        }
+      }
+    }
+    let noisy_pixels = film.height() * film.width() / 2;
+    assert!(noisy_samples > noisy_pixels * adaptive.max_samples_per_pixel / 2);
+  }
+}
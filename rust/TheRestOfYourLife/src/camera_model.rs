@@ -0,0 +1,471 @@
+use std::rc::Rc;
+
+use super::rtweekend;
+use super::aperture::Aperture;
+use super::ray::Ray;
+use super::vec3::{self, Point3, Vec3};
+
+// 相机模型：把胶片样本、透镜样本和时间样本映射为相机空间中的一条带权重的光线。
+// 相机空间中 x 轴向右、y 轴向上，相机朝 -z 方向看；Camera 负责把光线变换到世界空间，
+// 以及像素循环、滤波和积分器，因此任何模型都可以直接用于所有渲染循环。
+// 胶片样本是整幅图像上的连续位置，x 从左到右、y 从上到下，都在 [0, 1) 内；透镜样本和时间样本也在 [0, 1) 内。
+
+pub struct CameraRay {
+  pub ray: Ray,    // Ray leaving the camera, in camera space
+  pub weight: f64, // Factor on the light carried along the ray, zero if the film sample maps to no ray
+}
+
+pub trait CameraModel {
+  fn generate_ray(&self, film: (f64, f64), lens: (f64, f64), time: f64) -> CameraRay;
+}
+
+pub const PROJECTIONS: [&str; 7] = [
+  "perspective", "orthographic", "equirectangular", "cube-map",
+  "fisheye-equidistant", "fisheye-equisolid", "fisheye-stereographic",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Projection {
+  Perspective,                                    // Rays fan out from the lens through a viewport spanning vfov
+  Orthographic { view_height: f64 },              // Parallel rays through a viewport view_height world units tall
+  Equirectangular,                                // Longitude across the image and latitude down it, covering every direction
+  CubeMap,                                        // Six square 90 degree faces in a 3x2 grid, see CubeMap
+  Fisheye { fov: f64, mapping: FisheyeMapping }, // Image circle inscribed in the image, spanning fov degrees
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+  Equidistant,   // Distance from the image center proportional to the angle off the view direction
+  Equisolid,     // Area on the image proportional to solid angle
+  Stereographic, // Angles preserved, distance growing with the tangent of half the angle
+}
+
+// 移轴：平移镜头让视口偏离视线方向而成像平面保持与 w 垂直，建筑的竖直线因此保持平行。
+// 倾斜镜头时按沙姆定律，成像平面、透镜平面和对焦平面交于一条直线，对焦平面随之转动；这里直接给出对焦平面绕视线上对焦点转过的角度。
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct TiltShift {
+  pub shift: (f64, f64), // Offset of the viewport in fractions of its width to the right and of its height up
+  pub tilt: (f64, f64),  // Degrees the plane of focus turns about the horizontal axis, top away, and the vertical axis, right away
+}
+
+impl Projection {
+  pub fn model(
+    self,
+    aspect: f64,
+    vfov: f64,
+    focus_dist: f64,
+    defocus_angle: f64,
+    aperture: &Aperture,
+    tilt_shift: TiltShift,
+  ) -> Rc<dyn CameraModel> {
+    // 创建投影对应的相机模型，aspect 为图像宽高比。失焦和光圈只作用于透视和正交投影，移轴只作用于透视投影。
+    let lens_radius = focus_dist * rtweekend::degrees_to_radians(defocus_angle / 2.0).tan();
+    match self {
+      Self::Perspective => Rc::new(ThinLens {
+        viewport_height: 2.0 * rtweekend::degrees_to_radians(vfov / 2.0).tan() * focus_dist,
+        aspect,
+        focus_dist,
+        lens_radius,
+        aperture: aperture.clone(),
+        shift: tilt_shift.shift,
+        focus_normal: vec3::unit_vector(Vec3::new(
+          rtweekend::degrees_to_radians(tilt_shift.tilt.1).tan(),
+          rtweekend::degrees_to_radians(tilt_shift.tilt.0).tan(),
+          1.0,
+        )),
+      }),
+      Self::Orthographic { view_height } => Rc::new(Orthographic {
+        view_height,
+        aspect,
+        focus_dist,
+        lens_radius,
+        aperture: aperture.clone(),
+      }),
+      Self::Equirectangular => Rc::new(Equirectangular),
+      Self::CubeMap => Rc::new(CubeMap),
+      Self::Fisheye { fov, mapping } => Rc::new(Fisheye { fov, mapping, aspect }),
+    }
+  }
+}
+
+pub struct ThinLens {
+  pub viewport_height: f64, // Height of the viewport on the plane of focus
+  pub aspect: f64,          // Ratio of image width over height
+  pub focus_dist: f64,      // Distance from the lens to the plane of focus
+  pub lens_radius: f64,     // Radius of the lens, zero for a pinhole camera
+  pub aperture: Aperture,   // Shape of the lens opening
+  pub shift: (f64, f64),    // Offset of the viewport in fractions of its width to the right and of its height up
+  pub focus_normal: Vec3,   // Unit normal of the plane of focus through the focused point on the view axis, +z untilted
+}
+
+impl CameraModel for ThinLens {
+  fn generate_ray(&self, film: (f64, f64), lens: (f64, f64), time: f64) -> CameraRay {
+    // 光线从透镜上的一点出发，穿过对焦平面上与胶片位置对应的点。移轴相当于平移胶片位置，光轴仍在透镜中心。
+    let film = (film.0 + self.shift.0, film.1 - self.shift.1);
+    let target = viewport_point(film, self.viewport_height, self.aspect, self.focus_dist);
+    let Some((lx, ly)) = lens_sample(&self.aperture, self.lens_radius, film, self.aspect, lens) else {
+      return CameraRay { ray: Ray::new_with_time(Point3::default(), target, time), weight: 0.0 };
+    };
+    let origin = Point3::new(self.lens_radius * lx, self.lens_radius * ly, 0.0);
+
+    // 沿透镜中心到 target 的主光线求它与对焦平面的交点；倾斜后主光线可能不再与对焦平面相交，此时对焦在无穷远处。
+    let t = -self.focus_dist * self.focus_normal.z() / vec3::dot(target, self.focus_normal);
+    if t <= 0.0 || !t.is_finite() {
+      return CameraRay { ray: Ray::new_with_time(origin, target, time), weight: 1.0 };
+    }
+    let focus_point = t * target;
+    CameraRay { ray: Ray::new_with_time(origin, focus_point - origin, time), weight: 1.0 }
+  }
+}
+
+pub struct Orthographic {
+  pub view_height: f64,     // Height of the viewport in world units
+  pub aspect: f64,          // Ratio of image width over height
+  pub focus_dist: f64,      // Distance from the lens to the plane of focus
+  pub lens_radius: f64,     // Radius of the lens, zero for perfectly parallel rays
+  pub aperture: Aperture,   // Shape of the lens opening
+}
+
+impl CameraModel for Orthographic {
+  fn generate_ray(&self, film: (f64, f64), lens: (f64, f64), time: f64) -> CameraRay {
+    // 透镜中心是对焦点沿 z 轴投到相机平面上的点，因此所有光线平行，失焦时光线仍在对焦平面上汇聚。
+    let focus_point = viewport_point(film, self.view_height, self.aspect, self.focus_dist);
+    let Some((lx, ly)) = lens_sample(&self.aperture, self.lens_radius, film, self.aspect, lens) else {
+      return CameraRay { ray: Ray::new_with_time(Point3::default(), Vec3::new(0.0, 0.0, -1.0), time), weight: 0.0 };
+    };
+    let origin = Point3::new(focus_point.x() + self.lens_radius * lx, focus_point.y() + self.lens_radius * ly, 0.0);
+    CameraRay { ray: Ray::new_with_time(origin, focus_point - origin, time), weight: 1.0 }
+  }
+}
+
+pub struct Equirectangular;
+
+impl CameraModel for Equirectangular {
+  fn generate_ray(&self, film: (f64, f64), _lens: (f64, f64), time: f64) -> CameraRay {
+    // 图像中心对应视线方向，经度从左到右为 -180 到 180 度，纬度从上到下为 90 到 -90 度。
+    let longitude = (2.0 * film.0 - 1.0) * rtweekend::PI;
+    let latitude = (0.5 - film.1) * rtweekend::PI;
+    let (sin_lon, cos_lon) = longitude.sin_cos();
+    let (sin_lat, cos_lat) = latitude.sin_cos();
+    let direction = Vec3::new(cos_lat * sin_lon, sin_lat, -cos_lat * cos_lon);
+    CameraRay { ray: Ray::new_with_time(Point3::default(), direction, time), weight: 1.0 }
+  }
+}
+
+pub struct CubeMap;
+
+impl CameraModel for CubeMap {
+  fn generate_ray(&self, film: (f64, f64), _lens: (f64, f64), time: f64) -> CameraRay {
+    // 图像分为 3x2 个面，第一行依次为右、左、上，第二行为下、前、后。
+    // 每个面都是 90 度的针孔投影，上下两个面的图像上方分别朝向后方和前方。
+    let column = ((film.0 * 3.0) as usize).min(2);
+    let row = ((film.1 * 2.0) as usize).min(1);
+    let a = 2.0 * (film.0 * 3.0 - column as f64) - 1.0;
+    let b = 2.0 * (film.1 * 2.0 - row as f64) - 1.0;
+
+    // 每个面的视线方向、图像向右的方向和图像向下的方向。
+    let (x, y, z) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    let (forward, right, down) = match (row, column) {
+      (0, 0) => (x, z, -y),
+      (0, 1) => (-x, -z, -y),
+      (0, _) => (y, x, -z),
+      (_, 0) => (-y, x, z),
+      (_, 1) => (-z, x, -y),
+      (_, _) => (z, -x, -y),
+    };
+    let direction = forward + a * right + b * down;
+    CameraRay { ray: Ray::new_with_time(Point3::default(), direction, time), weight: 1.0 }
+  }
+}
+
+pub struct Fisheye {
+  pub fov: f64,                // Angle in degrees the image circle spans
+  pub mapping: FisheyeMapping, // How angles off the view direction map to distances on the image
+  pub aspect: f64,             // Ratio of image width over height
+}
+
+impl CameraModel for Fisheye {
+  fn generate_ray(&self, film: (f64, f64), _lens: (f64, f64), time: f64) -> CameraRay {
+    // 成像圆内切于图像，r 为到图像中心的距离与成像圆半径之比，圆外的样本权重为零。
+    let scale = 2.0 / self.aspect.min(1.0);
+    let dx = (film.0 - 0.5) * scale * self.aspect;
+    let dy = (film.1 - 0.5) * scale;
+    let r = (dx * dx + dy * dy).sqrt();
+    let forward = Vec3::new(0.0, 0.0, -1.0);
+    if r > 1.0 {
+      return CameraRay { ray: Ray::new_with_time(Point3::default(), forward, time), weight: 0.0 };
+    }
+
+    let theta_max = rtweekend::degrees_to_radians(self.fov / 2.0);
+    let theta = match self.mapping {
+      FisheyeMapping::Equidistant => r * theta_max,
+      FisheyeMapping::Equisolid => 2.0 * (r * (theta_max / 2.0).sin()).asin(),
+      FisheyeMapping::Stereographic => 2.0 * (r * (theta_max / 2.0).tan()).atan(),
+    };
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    let direction = if r > 0.0 {
+      cos_theta * forward + sin_theta / r * Vec3::new(dx, -dy, 0.0)
+    } else {
+      forward
+    };
+    CameraRay { ray: Ray::new_with_time(Point3::default(), direction, time), weight: 1.0 }
+  }
+}
+
+fn viewport_point(film: (f64, f64), viewport_height: f64, aspect: f64, focus_dist: f64) -> Point3 {
+  // Returns the point at film position on a viewport viewport_height tall, focus_dist in front of the camera.
+  let viewport_width = viewport_height * aspect;
+  Point3::new((film.0 - 0.5) * viewport_width, (0.5 - film.1) * viewport_height, -focus_dist)
+}
+
+fn lens_sample(aperture: &Aperture, lens_radius: f64, film: (f64, f64), aspect: f64, lens: (f64, f64)) -> Option<(f64, f64)> {
+  // 针孔相机没有光圈和镜筒，不采样也不渐晕。
+  if lens_radius <= 0.0 {
+    return Some((0.0, 0.0));
+  }
+  let half_diagonal = 0.5 * (aspect * aspect + 1.0).sqrt();
+  let film_offset = ((film.0 - 0.5) * aspect / half_diagonal, (0.5 - film.1) / half_diagonal);
+  aperture.sample(lens, film_offset)
+}
+
+pub fn concentric_disk(u: (f64, f64)) -> (f64, f64) {
+  // 将 [0, 1)^2 上的均匀样本同心映射到单位圆盘上，相邻样本映射后仍然相邻，保留分层。
+  let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+  if a == 0.0 && b == 0.0 {
+    return (0.0, 0.0);
+  }
+  let (r, theta) = if a.abs() > b.abs() {
+    (a, rtweekend::PI / 4.0 * (b / a))
+  } else {
+    (b, rtweekend::PI / 2.0 - rtweekend::PI / 4.0 * (a / b))
+  };
+  (r * theta.cos(), r * theta.sin())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StereoMode {
+  OffAxis,         // Parallel eyes with frusta sheared to meet at the convergence distance
+  ToeIn,           // Eyes rotated inwards to look at the point at the convergence distance
+  Omnidirectional, // Eyes on a circle, each panorama direction seen from the eye tangent to it
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+  TopBottom,  // Left eye in the top half of the image, right eye in the bottom half
+  SideBySide, // Left eye in the left half of the image, right eye in the right half
+}
+
+#[derive(Clone, Copy)]
+pub struct Stereo {
+  pub mode: StereoMode,
+  pub interocular: f64, // Distance between the eyes in world units
+  pub convergence: f64, // Distance from the eyes to the plane of zero parallax, unused by omnidirectional stereo
+  pub layout: StereoLayout,
+}
+
+impl Stereo {
+  pub fn eye_aspect(self, aspect: f64) -> f64 {
+    // Returns the aspect ratio of each eye's half of an image with the given aspect ratio.
+    match self.layout {
+      StereoLayout::TopBottom => aspect * 2.0,
+      StereoLayout::SideBySide => aspect / 2.0,
+    }
+  }
+
+  pub fn rig(self, eye: Rc<dyn CameraModel>) -> Rc<dyn CameraModel> {
+    // 用同一个单眼模型生成左右两眼的光线，两眼各占图像的一半。
+    Rc::new(StereoRig { stereo: self, eye })
+  }
+}
+
+impl StereoMode {
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::OffAxis => "off-axis",
+      Self::ToeIn => "toe-in",
+      Self::Omnidirectional => "ods",
+    }
+  }
+}
+
+impl std::str::FromStr for StereoMode {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "off-axis" => Ok(Self::OffAxis),
+      "toe-in" => Ok(Self::ToeIn),
+      "ods" => Ok(Self::Omnidirectional),
+      _ => Err(String::from("expected off-axis, toe-in or ods")),
+    }
+  }
+}
+
+impl std::str::FromStr for StereoLayout {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "top-bottom" => Ok(Self::TopBottom),
+      "side-by-side" => Ok(Self::SideBySide),
+      _ => Err(String::from("expected top-bottom or side-by-side")),
+    }
+  }
+}
+
+struct StereoRig {
+  stereo: Stereo,
+  eye: Rc<dyn CameraModel>, // Model of a single eye centered on the rig
+}
+
+impl CameraModel for StereoRig {
+  fn generate_ray(&self, film: (f64, f64), lens: (f64, f64), time: f64) -> CameraRay {
+    // 先确定样本属于哪只眼睛，并换算为该眼睛半幅图像上的胶片位置。
+    let (left, film) = match self.stereo.layout {
+      StereoLayout::TopBottom if film.1 < 0.5 => (true, (film.0, 2.0 * film.1)),
+      StereoLayout::TopBottom => (false, (film.0, 2.0 * film.1 - 1.0)),
+      StereoLayout::SideBySide if film.0 < 0.5 => (true, (2.0 * film.0, film.1)),
+      StereoLayout::SideBySide => (false, (2.0 * film.0 - 1.0, film.1)),
+    };
+    let CameraRay { ray, weight } = self.eye.generate_ray(film, lens, time);
+    let (o, d) = (ray.origin(), ray.direction());
+
+    // s 为眼睛沿 x 轴相对于装置中心的偏移。
+    let s = if left { -0.5 } else { 0.5 } * self.stereo.interocular;
+    let (origin, direction) = match self.stereo.mode {
+      StereoMode::OffAxis => {
+        // 平移眼睛并剪切视锥：深度为 -z 的点沿 x 平移 s * (1 + z / convergence)，
+        // 因此透镜平面上平移 s，会聚平面上保持不动，两眼的画面在会聚平面上重合。
+        let c = self.stereo.convergence;
+        (
+          Point3::new(o.x() + s * (1.0 + o.z() / c), o.y(), o.z()),
+          Vec3::new(d.x() + s * d.z() / c, d.y(), d.z()),
+        )
+      },
+      StereoMode::ToeIn => {
+        // 绕 y 轴旋转眼睛，使其视线指向会聚距离处的装置中心。
+        let hypot = (s * s + self.stereo.convergence * self.stereo.convergence).sqrt();
+        let (sin_phi, cos_phi) = (s / hypot, self.stereo.convergence / hypot);
+        let rotate = |v: Vec3| Vec3::new(v.x() * cos_phi + v.z() * sin_phi, v.y(), -v.x() * sin_phi + v.z() * cos_phi);
+        (rotate(o) + Vec3::new(s, 0.0, 0.0), rotate(d))
+      },
+      StereoMode::Omnidirectional => {
+        // 眼睛位于直径为瞳距的水平圆上，沿光线水平方向的右侧偏移，竖直的光线不偏移。
+        let horizontal = (d.x() * d.x() + d.z() * d.z()).sqrt();
+        let right = if horizontal > 0.0 { Vec3::new(-d.z(), 0.0, d.x()) / horizontal } else { Vec3::default() };
+        (o + s * right, d)
+      },
+    };
+    CameraRay { ray: Ray::new_with_time(origin, direction, ray.time()), weight }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn model(projection: Projection, aspect: f64) -> Rc<dyn CameraModel> {
+    projection.model(aspect, 90.0, 1.0, 0.0, &Aperture::default(), TiltShift::default())
+  }
+
+  fn assert_near(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+  }
+
+  fn direction(model: &dyn CameraModel, film: (f64, f64)) -> Vec3 {
+    let camera_ray = model.generate_ray(film, (0.5, 0.5), 0.0);
+    assert_eq!(camera_ray.weight, 1.0);
+    vec3::unit_vector(camera_ray.ray.direction())
+  }
+
+  #[test]
+  fn orthographic_rays_are_parallel_across_the_viewport() {
+    let orthographic = model(Projection::Orthographic { view_height: 2.0 }, 2.0);
+    for (film, origin) in [
+      ((0.5, 0.5), Point3::new(0.0, 0.0, 0.0)),
+      ((0.0, 0.0), Point3::new(-2.0, 1.0, 0.0)),
+      ((1.0, 1.0), Point3::new(2.0, -1.0, 0.0)),
+    ] {
+      let camera_ray = orthographic.generate_ray(film, (0.5, 0.5), 0.0);
+      assert_near(camera_ray.ray.origin(), origin);
+      assert_near(vec3::unit_vector(camera_ray.ray.direction()), Vec3::new(0.0, 0.0, -1.0));
+    }
+  }
+
+  #[test]
+  fn equirectangular_maps_longitude_and_latitude() {
+    let equirectangular = model(Projection::Equirectangular, 2.0);
+    for (film, expected) in [
+      ((0.5, 0.5), Vec3::new(0.0, 0.0, -1.0)),
+      ((0.75, 0.5), Vec3::new(1.0, 0.0, 0.0)),
+      ((0.25, 0.5), Vec3::new(-1.0, 0.0, 0.0)),
+      ((0.0, 0.5), Vec3::new(0.0, 0.0, 1.0)),
+      ((0.5, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+      ((0.5, 1.0), Vec3::new(0.0, -1.0, 0.0)),
+    ] {
+      assert_near(direction(equirectangular.as_ref(), film), expected);
+    }
+  }
+
+  #[test]
+  fn cube_map_face_centers_look_along_the_axes() {
+    // 第一行依次为右、左、上，第二行为下、前、后；前方为 -z。
+    let cube_map = model(Projection::CubeMap, 1.5);
+    for (film, expected) in [
+      ((1.0 / 6.0, 0.25), Vec3::new(1.0, 0.0, 0.0)),
+      ((3.0 / 6.0, 0.25), Vec3::new(-1.0, 0.0, 0.0)),
+      ((5.0 / 6.0, 0.25), Vec3::new(0.0, 1.0, 0.0)),
+      ((1.0 / 6.0, 0.75), Vec3::new(0.0, -1.0, 0.0)),
+      ((3.0 / 6.0, 0.75), Vec3::new(0.0, 0.0, -1.0)),
+      ((5.0 / 6.0, 0.75), Vec3::new(0.0, 0.0, 1.0)),
+    ] {
+      assert_near(direction(cube_map.as_ref(), film), expected);
+    }
+  }
+
+  #[test]
+  fn fisheye_center_looks_ahead_and_circle_edge_is_at_half_the_fov() {
+    for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid, FisheyeMapping::Stereographic] {
+      let fisheye = model(Projection::Fisheye { fov: 120.0, mapping }, 1.0);
+      assert_near(direction(fisheye.as_ref(), (0.5, 0.5)), Vec3::new(0.0, 0.0, -1.0));
+
+      // 成像圆右边缘和上边缘的光线偏离视线 60 度。
+      let half_fov = rtweekend::degrees_to_radians(60.0);
+      assert_near(direction(fisheye.as_ref(), (1.0, 0.5)), Vec3::new(half_fov.sin(), 0.0, -half_fov.cos()));
+      assert_near(direction(fisheye.as_ref(), (0.5, 0.0)), Vec3::new(0.0, half_fov.sin(), -half_fov.cos()));
+      assert_eq!(fisheye.generate_ray((1.0, 0.0), (0.5, 0.5), 0.0).weight, 0.0);
+    }
+  }
+
+  #[test]
+  fn tilted_plane_of_focus_is_sharp() {
+    // 穿过同一胶片位置、从透镜不同位置出发的光线在倾斜后的对焦平面上交于一点，弥散圆为零；在未倾斜的平面上则不交于一点。
+    let focus_dist = 2.0;
+    let tilt_shift = TiltShift { shift: (0.1, 0.0), tilt: (20.0, 10.0) };
+    let lens = Projection::Perspective.model(1.5, 60.0, focus_dist, 10.0, &Aperture::default(), tilt_shift);
+    let normal = vec3::unit_vector(Vec3::new(
+      rtweekend::degrees_to_radians(10.0).tan(),
+      rtweekend::degrees_to_radians(20.0).tan(),
+      1.0,
+    ));
+    let on_plane = |ray: &Ray, normal: Vec3| {
+      // 平面过视线上的对焦点 (0, 0, -focus_dist)，法线为 normal。
+      let t = (-focus_dist * normal.z() - vec3::dot(ray.origin(), normal)) / vec3::dot(ray.direction(), normal);
+      ray.at(t)
+    };
+
+    for film in [(0.5, 0.5), (0.2, 0.3), (0.8, 0.7)] {
+      let rays: Vec<Ray> = [(0.1, 0.2), (0.9, 0.7), (0.4, 0.95)]
+        .into_iter()
+        .map(|u| lens.generate_ray(film, u, 0.0).ray)
+        .collect();
+      assert!(rays.iter().any(|ray| ray.origin().length() > 0.01));
+
+      let focused = on_plane(&rays[0], normal);
+      assert!(rays.iter().all(|ray| (on_plane(ray, normal) - focused).length() < 1e-9));
+      let untilted = Vec3::new(0.0, 0.0, 1.0);
+      let blurred = on_plane(&rays[0], untilted);
+      assert!(rays.iter().any(|ray| (on_plane(ray, untilted) - blurred).length() > 1e-3));
+    }
+  }
+}
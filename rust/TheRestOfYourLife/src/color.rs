@@ -16,7 +16,17 @@ pub fn linear_to_gamma(linear_component: f64) -> f64 {
 }
 
 impl Color {
+  pub fn luminance(&self) -> f64 {
+    // Returns the Rec. 709 luminance of a linear color.
+    0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+  }
+
   pub fn write_color(&self, out: &mut dyn Write, samples_per_pixel: usize) -> std::io::Result<()> {
+    let [r, g, b] = self.to_bytes(samples_per_pixel);
+    writeln!(out, "{} {} {}", r, g, b)
+  }
+
+  pub fn to_bytes(&self, samples_per_pixel: usize) -> [u8; 3] {
     let r = self.x();
     let g = self.y();
     let b = self.z();
@@ -37,10 +47,11 @@ impl Color {
     let g = linear_to_gamma(g);
     let b = linear_to_gamma(b);
 
-    // Write the translated [0,255] value of each color component.
-    writeln!(out, "{} {} {}",
-      (256.0 * INTENSITY.clamp(r)) as i32,
-      (256.0 * INTENSITY.clamp(g)) as i32,
-      (256.0 * INTENSITY.clamp(b)) as i32)
+    // Return the translated [0,255] value of each color component.
+    [
+      (256.0 * INTENSITY.clamp(r)) as u8,
+      (256.0 * INTENSITY.clamp(g)) as u8,
+      (256.0 * INTENSITY.clamp(b)) as u8,
+    ]
   }
 }
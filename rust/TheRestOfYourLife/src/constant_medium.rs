@@ -2,10 +2,12 @@ use std::rc::Rc;
 
 use super::rtweekend;
 use super::hittable::{
+    self,
     Hittable,
     HitRecord,
 };
 use super::material::{
+  self,
   Material,
   Isotropic,
 };
@@ -18,32 +20,43 @@ use super::interval::{
   self,
   Interval,
 };
+use super::stats;
 
 pub struct ConstantMedium {
   boundary: Rc<dyn Hittable>,
   neg_inv_density: f64,
   phase_function: Rc<dyn Material>,
+  id: usize,
+  material_id: usize,
 }
 
 impl ConstantMedium {
   pub fn new(b: Rc<dyn Hittable>, d: f64, a: Rc<dyn Texture>) -> Self {
+    let phase_function: Rc<dyn Material> = Rc::new(Isotropic::new(a));
     Self {
       boundary: b,
       neg_inv_density: -1.0 / d,
-      phase_function: Rc::new(Isotropic::new(a)),
+      id: hittable::next_object_id(),
+      material_id: material::material_id(&phase_function),
+      phase_function,
     }
   }
   pub fn new_with_color(b: Rc<dyn Hittable>, d: f64, c: Color) -> Self {
+    let phase_function: Rc<dyn Material> = Rc::new(Isotropic::new_with_color(c));
     Self {
       boundary: b,
       neg_inv_density: -1.0 / d,
-      phase_function: Rc::new(Isotropic::new_with_color(c)),
+      id: hittable::next_object_id(),
+      material_id: material::material_id(&phase_function),
+      phase_function,
     }
   }
 }
 
 impl Hittable for ConstantMedium {
   fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+    stats::count(|c| c.medium_tests += 1);
+
     // Print occasional samples when debugging. To enable, set enableDebug true.
     const ENABLE_DEBUG: bool = false;
     let debugging = ENABLE_DEBUG && rtweekend::random_double() < 0.00001;
@@ -98,6 +111,8 @@ impl Hittable for ConstantMedium {
     rec.normal = Vec3::new(1.0, 0.0, 0.0); // arbitrary
     rec.front_face = true; // also arbitrary
     rec.mat = Some(Rc::clone(&self.phase_function));
+    rec.object_id = self.id;
+    rec.material_id = self.material_id;
 
     true
   }
@@ -0,0 +1,164 @@
+use super::color::Color;
+use super::framebuffer::Framebuffer;
+use super::vec3::Vec3;
+
+// 边缘保持的 à-trous 小波滤波器（Dammertz 等，2010）：以逐次加倍的间隔重复应用 5x5 的 B3 样条核，
+// 并用颜色、法线和深度的差异来减小跨越边缘的权重。滤波前先除以反照率，只对光照部分滤波，
+// 滤波后再乘回反照率，这样纹理细节不会被模糊。
+pub struct AtrousDenoiser {
+  pub iterations: usize,  // Count of filter passes, the footprint doubles on each pass
+  pub sigma_color: f64,   // Color difference at which weights fall off, halved on each pass
+  pub sigma_normal: f64,  // Normal difference at which weights fall off
+  pub sigma_depth: f64,   // Relative depth difference at which weights fall off
+}
+
+impl Default for AtrousDenoiser {
+  fn default() -> Self {
+    Self {
+      iterations: 5,
+      sigma_color: 2.0,
+      sigma_normal: 0.1,
+      sigma_depth: 0.05,
+    }
+  }
+}
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// 反照率低于该值的通道不做解调，以免放大噪声。
+const MIN_ALBEDO: f64 = 0.01;
+
+struct Features {
+  albedo: Color,
+  normal: Vec3,
+  depth: f64,
+}
+
+impl AtrousDenoiser {
+  fn demodulate(c: Color, albedo: Color) -> Color {
+    let channel = |c: f64, a: f64| if a > MIN_ALBEDO { c / a } else { c };
+    Color::new(channel(c.x(), albedo.x()), channel(c.y(), albedo.y()), channel(c.z(), albedo.z()))
+  }
+
+  fn modulate(c: Color, albedo: Color) -> Color {
+    let channel = |c: f64, a: f64| if a > MIN_ALBEDO { c * a } else { c };
+    Color::new(channel(c.x(), albedo.x()), channel(c.y(), albedo.y()), channel(c.z(), albedo.z()))
+  }
+
+  fn is_finite(c: Color) -> bool {
+    c.x().is_finite() && c.y().is_finite() && c.z().is_finite()
+  }
+
+  pub fn denoise(&self, film: &mut Framebuffer, samples_per_pixel: usize) {
+    // 就地替换 film 中的像素，film 必须带有 AOV。
+    let width = film.width();
+    let height = film.height();
+
+    let mut features = Vec::with_capacity(width * height);
+    let mut color = Vec::with_capacity(width * height);
+    for j in 0..height {
+      for i in 0..width {
+        let scale = 1.0 / film.sample_count(i, j, samples_per_pixel) as f64;
+        let aov = film.aov(i, j);
+        let albedo = aov.albedo * scale;
+        let normal = aov.normal * scale;
+        let c = film.pixel(i, j) * scale;
+        color.push(Self::demodulate(c, albedo));
+        features.push(Features { albedo, normal, depth: aov.depth * scale });
+      }
+    }
+
+    let mut filtered = vec![Color::default(); width * height];
+    for iteration in 0..self.iterations {
+      let step = 1_i64 << iteration;
+      let sigma_color = self.sigma_color / (1 << iteration) as f64;
+
+      for j in 0..height {
+        for i in 0..width {
+          let p = j * width + i;
+          let fp = &features[p];
+          let center_finite = Self::is_finite(color[p]);
+          let mut sum = Color::default();
+          let mut weight_sum = 0.0;
+
+          for (dy, ky) in KERNEL.iter().enumerate() {
+            let y = j as i64 + (dy as i64 - 2) * step;
+            if y < 0 || y >= height as i64 {
+              continue;
+            }
+            for (dx, kx) in KERNEL.iter().enumerate() {
+              let x = i as i64 + (dx as i64 - 2) * step;
+              if x < 0 || x >= width as i64 {
+                continue;
+              }
+              let q = y as usize * width + x as usize;
+              let fq = &features[q];
+
+              // NaN 或无穷大的像素不参与滤波，否则会污染整个核覆盖的区域；
+              // 中心像素本身不是有限值时，只按法线和深度从邻域中重建。
+              if !Self::is_finite(color[q]) {
+                continue;
+              }
+              let color_distance = if center_finite { (color[p] - color[q]).length_squared() } else { 0.0 };
+              let normal_distance = (fp.normal - fq.normal).length_squared();
+              let depth_distance = (fp.depth - fq.depth).abs() / fp.depth.max(fq.depth).max(1e-8);
+
+              let w = kx * ky
+                * (-color_distance / (sigma_color * sigma_color)).exp()
+                * (-normal_distance / (self.sigma_normal * self.sigma_normal)).exp()
+                * (-depth_distance * depth_distance / (self.sigma_depth * self.sigma_depth)).exp();
+              sum += w * color[q];
+              weight_sum += w;
+            }
+          }
+
+          // 中心像素是有限值时其权重总是大于零；只有整个核内都不是有限值时 weight_sum 才为零。
+          filtered[p] = if weight_sum > 0.0 { sum / weight_sum } else { color[p] };
+        }
+      }
+      std::mem::swap(&mut color, &mut filtered);
+    }
+
+    for j in 0..height {
+      for i in 0..width {
+        let p = j * width + i;
+        let c = Self::modulate(color[p], features[p].albedo);
+        film.set(i, j, c * film.sample_count(i, j, samples_per_pixel) as f64);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::framebuffer::Aov;
+
+  #[test]
+  fn non_finite_pixels_do_not_spread() {
+    let mut film = Framebuffer::new_with_aovs(8, 8);
+    let aov = Aov {
+      albedo: Color::new(0.5, 0.5, 0.5),
+      normal: Vec3::new(0.0, 0.0, 1.0),
+      depth: 1.0,
+      ..Default::default()
+    };
+    for j in 0..8 {
+      for i in 0..8 {
+        film.set(i, j, Color::new(0.25, 0.25, 0.25));
+        film.add_aov(i, j, &aov);
+      }
+    }
+    film.set(3, 3, Color::new(f64::NAN, 0.0, 0.0));
+    film.set(4, 4, Color::new(f64::INFINITY, 0.0, 0.0));
+
+    AtrousDenoiser::default().denoise(&mut film, 1);
+
+    for j in 0..8 {
+      for i in 0..8 {
+        let c = film.pixel(i, j);
+        assert!((c - Color::new(0.25, 0.25, 0.25)).length() < 1e-9, "pixel ({}, {}) is {:?}", i, j, (c.x(), c.y(), c.z()));
+      }
+    }
+  }
+}
@@ -0,0 +1,46 @@
+use std::io::BufReader;
+use std::process::{Command, Stdio};
+
+use super::progressive::RenderState;
+
+// 分布式渲染的协调端：以工作模式启动本程序的多个副本，每个副本渐进式地渲染自己分到的图块，
+// 并把渲染状态以检查点格式写到标准输出；协调端通过管道读取并合并它们。
+// 工作进程使用相同的种子和场景，因此合并后的图像与单进程渐进式渲染的结果相同。
+
+pub fn render_with_workers(workers: usize, worker_args: &[String]) -> std::io::Result<RenderState> {
+  // 每个工作进程的命令行为 worker_args 加上 "--worker index/workers"。
+  let exe = std::env::current_exe()?;
+  let mut children = Vec::with_capacity(workers);
+  for index in 0..workers {
+    let child = Command::new(&exe)
+      .args(worker_args)
+      .arg("--worker")
+      .arg(format!("{}/{}", index, workers))
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .spawn()?;
+    children.push(child);
+  }
+
+  // 工作进程只在渲染结束时写出结果，因此依次读取各个管道不会死锁。
+  let mut merged: Option<RenderState> = None;
+  for (index, mut child) in children.into_iter().enumerate() {
+    let stdout = child.stdout.take().expect("worker stdout is piped");
+    let state = RenderState::read_checkpoint(&mut BufReader::new(stdout));
+    let status = child.wait()?;
+    if !status.success() {
+      return Err(std::io::Error::other(format!("worker {} exited with {}", index, status)));
+    }
+
+    let state = state?;
+    merged = match merged {
+      None => Some(state),
+      Some(mut m) => {
+        m.merge(&state).map_err(std::io::Error::other)?;
+        Some(m)
+      },
+    };
+  }
+
+  merged.ok_or_else(|| std::io::Error::other("no workers"))
+}
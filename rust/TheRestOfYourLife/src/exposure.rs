@@ -0,0 +1,145 @@
+// 物理曝光：由真实相机的参数和快门时间推出视场角、失焦程度和画面亮度，快门决定运动模糊的时间跨度。
+// 场景的时间以秒为单位，运动物体在时间 0 到 1 之间从第一个位置移到第二个位置。
+// 渲染出的辐射亮度视为以 cd/m^2 为单位的亮度，亮度缩放遵循反射式测光表的曝光方程 L t S / (K N^2) = 1，
+// 测光正确的场景平均亮度落在 18% 灰。
+
+const METER_CALIBRATION: f64 = 12.5; // Reflected-light meter calibration constant K
+const MIDDLE_GRAY: f64 = 0.18;       // Value a correctly metered average luminance maps to
+
+#[derive(Clone, Copy)]
+pub struct Exposure {
+  pub f_number: f64,      // Focal length over the aperture diameter
+  pub focal_length: f64,  // Focal length of the lens in mm
+  pub sensor_width: f64,  // Width of the sensor in mm, its height follows from the image aspect ratio
+  pub mm_per_unit: f64,   // Millimetres in one world unit, relating the aperture to scene distances
+  pub iso: f64,           // Sensitivity of the sensor, brightness is proportional to it
+}
+
+impl Default for Exposure {
+  fn default() -> Self {
+    Self {
+      f_number: 8.0,
+      focal_length: 50.0,
+      sensor_width: 36.0,
+      mm_per_unit: 1.0,
+      iso: 100.0,
+    }
+  }
+}
+
+impl Exposure {
+  pub fn vfov(&self, aspect: f64) -> f64 {
+    // Returns the vertical field of view in degrees of the sensor behind a lens focused at infinity.
+    let sensor_height = self.sensor_width / aspect;
+    2.0 * (sensor_height / (2.0 * self.focal_length)).atan().to_degrees()
+  }
+
+  pub fn defocus_angle(&self, focus_dist: f64) -> f64 {
+    // Returns the angle in degrees the aperture subtends from the plane of focus.
+    let aperture_radius = self.focal_length / (2.0 * self.f_number) / self.mm_per_unit;
+    2.0 * (aperture_radius / focus_dist).atan().to_degrees()
+  }
+
+  pub fn brightness(&self, shutter: &Shutter) -> f64 {
+    // Returns the factor turning scene luminance into pixel values, each row being exposed for the shutter's exposure time.
+    MIDDLE_GRAY * shutter.exposure_time() * self.iso / (METER_CALIBRATION * self.f_number * self.f_number)
+  }
+}
+
+// 全局快门让所有行在同一段时间内曝光；卷帘快门逐行读出，每行曝光同样长的时间，
+// 但开始时间从顶行到底行线性推迟 readout，快速运动的物体因此发生倾斜和形变。
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShutterMode {
+  Global,                   // Every row exposed over the same interval
+  Rolling { readout: f64 }, // Rows exposed one after another, the bottom row starting readout seconds after the top row
+}
+
+#[derive(Clone, Copy)]
+pub struct Shutter {
+  pub open: f64,         // Time in seconds the shutter opens, for the top row of a rolling shutter
+  pub close: f64,        // Time in seconds the shutter closes, for the top row of a rolling shutter
+  pub mode: ShutterMode, // How the exposure of a row depends on its position
+}
+
+impl Default for Shutter {
+  fn default() -> Self {
+    Self {
+      open: 0.0,
+      close: 1.0,
+      mode: ShutterMode::Global,
+    }
+  }
+}
+
+impl Shutter {
+  pub fn exposure_time(&self) -> f64 {
+    // Returns how long each row is exposed in seconds.
+    self.close - self.open
+  }
+
+  pub fn readout(&self) -> f64 {
+    // Returns how much later the bottom row starts its exposure than the top row.
+    match self.mode {
+      ShutterMode::Global => 0.0,
+      ShutterMode::Rolling { readout } => readout,
+    }
+  }
+
+  pub fn end(&self) -> f64 {
+    // Returns the time the last row stops being exposed.
+    self.close + self.readout()
+  }
+
+  pub fn sample(&self, row: f64, u: f64) -> f64 {
+    // Maps u in [0, 1) to a time the row at row in [0, 1), top to bottom, is exposed.
+    self.open + row * self.readout() + u * self.exposure_time()
+  }
+
+  pub fn sample_frame(&self, u: f64) -> f64 {
+    // Maps u in [0, 1) to a time any row of the frame is exposed.
+    self.open + u * (self.end() - self.open)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn brightness_maps_metered_luminance_to_middle_gray() {
+    // f/16、1/100 s、ISO 100 对应 EV100 = log2(16^2 / 0.01) = log2(25600)，
+    // 测光表在该曝光下读到的亮度为 K 2^EV / S = 12.5 * 25600 / 100 = 3200 cd/m^2。
+    let exposure = Exposure { f_number: 16.0, iso: 100.0, ..Default::default() };
+    let shutter = Shutter { open: 0.0, close: 0.01, mode: ShutterMode::Global };
+    let brightness = exposure.brightness(&shutter);
+    assert!((brightness - 0.18 / 3200.0).abs() < 1e-15);
+    assert!((brightness * 3200.0 - MIDDLE_GRAY).abs() < 1e-12);
+
+    // 感光度加倍或光圈开大一档，亮度都加倍。
+    let faster = Exposure { iso: 200.0, ..exposure };
+    assert!((faster.brightness(&shutter) - 2.0 * brightness).abs() < 1e-15);
+    let wider = Exposure { f_number: 16.0 / 2.0_f64.sqrt(), ..exposure };
+    assert!((wider.brightness(&shutter) - 2.0 * brightness).abs() < 1e-15);
+  }
+
+  #[test]
+  fn rolling_shutter_rows_start_in_order_and_span_the_frame() {
+    // 每行的曝光开始时间自上而下递增，各行合起来正好覆盖从开门到最后一行结束的整段时间。
+    let shutter = Shutter { open: 0.5, close: 0.75, mode: ShutterMode::Rolling { readout: 0.5 } };
+    let rows: Vec<f64> = (0..=10).map(|k| k as f64 / 10.0).collect();
+    for pair in rows.windows(2) {
+      assert!(shutter.sample(pair[1], 0.0) > shutter.sample(pair[0], 0.0));
+      assert!(shutter.sample(pair[1], 1.0) > shutter.sample(pair[0], 1.0));
+    }
+    for &row in rows.iter() {
+      let (start, end) = (shutter.sample(row, 0.0), shutter.sample(row, 1.0));
+      assert!((end - start - shutter.exposure_time()).abs() < 1e-12);
+      assert!(start >= shutter.open && end <= shutter.end() + 1e-12);
+    }
+    assert_eq!(shutter.sample(0.0, 0.0), shutter.open);
+    assert!((shutter.sample(1.0, 1.0) - shutter.end()).abs() < 1e-12);
+    assert_eq!(shutter.sample_frame(0.0), shutter.open);
+    assert!((shutter.sample_frame(1.0) - shutter.end()).abs() < 1e-12);
+  }
+}
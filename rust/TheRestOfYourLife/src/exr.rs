@@ -0,0 +1,108 @@
+use std::io::Write;
+
+// 最小化的 OpenEXR 写入器：单部件、扫描线存储、不压缩，每个通道为 32 位浮点数或无符号整数。
+// 通道名可以用 "层.通道" 的形式组织成多个层，例如 "albedo.R"。
+
+pub enum ExrPixels<'a> {
+  Float(&'a [f32]),
+  Uint(&'a [u32]),
+}
+
+pub struct ExrChannel<'a> {
+  pub name: String,          // Channel name, dot separated for layered channels
+  pub pixels: ExrPixels<'a>, // Row-major pixel values, width * height of them
+}
+
+impl ExrPixels<'_> {
+  fn pixel_type(&self) -> i32 {
+    match self {
+      ExrPixels::Uint(_) => 0,
+      ExrPixels::Float(_) => 2,
+    }
+  }
+
+  fn write_row(&self, out: &mut Vec<u8>, start: usize, width: usize) {
+    match self {
+      ExrPixels::Uint(values) => {
+        for v in values[start..start + width].iter() {
+          out.extend_from_slice(&v.to_le_bytes());
+        }
+      },
+      ExrPixels::Float(values) => {
+        for v in values[start..start + width].iter() {
+          out.extend_from_slice(&v.to_le_bytes());
+        }
+      },
+    }
+  }
+}
+
+fn write_attribute(out: &mut Vec<u8>, name: &str, type_name: &str, value: &[u8]) {
+  out.extend_from_slice(name.as_bytes());
+  out.push(0);
+  out.extend_from_slice(type_name.as_bytes());
+  out.push(0);
+  out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+  out.extend_from_slice(value);
+}
+
+fn box2i(width: usize, height: usize) -> Vec<u8> {
+  let mut value = Vec::with_capacity(16);
+  for v in [0, 0, width as i32 - 1, height as i32 - 1] {
+    value.extend_from_slice(&v.to_le_bytes());
+  }
+  value
+}
+
+pub fn write_exr(out: &mut dyn Write, width: usize, height: usize, channels: &mut [ExrChannel]) -> std::io::Result<()> {
+  // EXR 要求通道按名称的字节序排列，像素数据也按同样的顺序存储。
+  channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+  let mut header = Vec::new();
+  header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+  header.extend_from_slice(&2_i32.to_le_bytes());
+
+  let mut chlist = Vec::new();
+  for channel in channels.iter() {
+    chlist.extend_from_slice(channel.name.as_bytes());
+    chlist.push(0);
+    chlist.extend_from_slice(&channel.pixels.pixel_type().to_le_bytes());
+    chlist.extend_from_slice(&[0, 0, 0, 0]);
+    chlist.extend_from_slice(&1_i32.to_le_bytes());
+    chlist.extend_from_slice(&1_i32.to_le_bytes());
+  }
+  chlist.push(0);
+
+  write_attribute(&mut header, "channels", "chlist", &chlist);
+  write_attribute(&mut header, "compression", "compression", &[0]);
+  write_attribute(&mut header, "dataWindow", "box2i", &box2i(width, height));
+  write_attribute(&mut header, "displayWindow", "box2i", &box2i(width, height));
+  write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+  write_attribute(&mut header, "pixelAspectRatio", "float", &1.0_f32.to_le_bytes());
+  write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+  write_attribute(&mut header, "screenWindowWidth", "float", &1.0_f32.to_le_bytes());
+  header.push(0);
+
+  // 每条扫描线是一个块：y 坐标、数据字节数，然后依次是每个通道的一整行像素。
+  let row_bytes = 4 * width * channels.len();
+  let chunk_size = 8 + row_bytes;
+  let table_end = header.len() + 8 * height;
+
+  out.write_all(&header)?;
+  for y in 0..height {
+    out.write_all(&((table_end + y * chunk_size) as u64).to_le_bytes())?;
+  }
+
+  let mut chunk = Vec::with_capacity(chunk_size);
+  for y in 0..height {
+    chunk.clear();
+    chunk.extend_from_slice(&(y as i32).to_le_bytes());
+    chunk.extend_from_slice(&(row_bytes as i32).to_le_bytes());
+    for channel in channels.iter() {
+      channel.pixels.write_row(&mut chunk, y * width, width);
+    }
+    out.write_all(&chunk)?;
+  }
+
+  Ok(())
+}
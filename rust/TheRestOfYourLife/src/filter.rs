@@ -0,0 +1,145 @@
+use super::rtweekend;
+
+// 像素重建滤波器。每个样本按滤波器权重累加到半径范围内的所有像素上，
+// 像素值为加权和除以权重和。二维滤波器是两个一维滤波器的乘积，并归一化为积分为一，
+// 因此均匀分布的样本在每个像素上的期望权重和等于样本数。
+
+pub const FILTERS: [&str; 6] = ["box", "tent", "gaussian", "mitchell", "lanczos", "blackman-harris"];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterKind {
+  Box,            // Equal weight everywhere within the radius
+  Tent,           // Weight falling linearly to zero at the radius
+  Gaussian,       // Gaussian with a standard deviation of a third of the radius, shifted to zero at the radius
+  Mitchell,       // Mitchell-Netravali cubic with B = C = 1/3
+  Lanczos,        // Sinc windowed by a sinc stretched to the radius
+  BlackmanHarris, // Four-term Blackman-Harris window
+}
+
+#[derive(Clone, Copy)]
+pub struct Filter {
+  pub kind: FilterKind,
+  pub radius: f64, // Distance in pixels beyond which samples have no weight
+  scale: f64,      // Reciprocal of the integral of the one-dimensional filter
+}
+
+impl Default for Filter {
+  fn default() -> Self {
+    // 半径为半个像素的盒式滤波器，即每个样本只计入它所在的像素。
+    Self::new("box", None).unwrap()
+  }
+}
+
+impl Filter {
+  pub fn new(name: &str, radius: Option<f64>) -> Option<Self> {
+    // 按名称创建滤波器，未给出半径时使用该滤波器常用的半径。
+    let (kind, default_radius) = match name {
+      "box" => (FilterKind::Box, 0.5),
+      "tent" => (FilterKind::Tent, 1.0),
+      "gaussian" => (FilterKind::Gaussian, 1.5),
+      "mitchell" => (FilterKind::Mitchell, 2.0),
+      "lanczos" => (FilterKind::Lanczos, 3.0),
+      "blackman-harris" => (FilterKind::BlackmanHarris, 2.0),
+      _ => return None,
+    };
+    let mut filter = Self {
+      kind,
+      radius: radius.unwrap_or(default_radius),
+      scale: 1.0,
+    };
+
+    // 用中点法数值积分求一维滤波器的积分。
+    const STEPS: usize = 1024;
+    let dx = 2.0 * filter.radius / STEPS as f64;
+    let integral: f64 = (0..STEPS).map(|k| filter.evaluate_unscaled(-filter.radius + (k as f64 + 0.5) * dx) * dx).sum();
+    filter.scale = 1.0 / integral;
+    Some(filter)
+  }
+
+  pub fn is_pixel_box(&self) -> bool {
+    // Returns whether the filter only counts each sample in its own pixel, with equal weight.
+    self.kind == FilterKind::Box && self.radius == 0.5
+  }
+
+  pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+    // Returns the weight of a sample dx,dy pixels away from a pixel center.
+    self.evaluate_1d(dx) * self.evaluate_1d(dy)
+  }
+
+  fn evaluate_1d(&self, x: f64) -> f64 {
+    self.scale * self.evaluate_unscaled(x)
+  }
+
+  fn evaluate_unscaled(&self, x: f64) -> f64 {
+    let r = self.radius;
+    let x = x.abs();
+    if x > r {
+      return 0.0;
+    }
+
+    match self.kind {
+      FilterKind::Box => 1.0,
+      FilterKind::Tent => r - x,
+      FilterKind::Gaussian => {
+        let sigma = r / 3.0;
+        let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+        (gaussian(x) - gaussian(r)).max(0.0)
+      },
+      FilterKind::Mitchell => {
+        // 将 [0, r] 映射到三次多项式的定义域 [0, 2]。
+        let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+        let t = 2.0 * x / r;
+        if t < 1.0 {
+          ((12.0 - 9.0 * b - 6.0 * c) * t * t * t + (-18.0 + 12.0 * b + 6.0 * c) * t * t + (6.0 - 2.0 * b)) / 6.0
+        } else {
+          ((-b - 6.0 * c) * t * t * t + (6.0 * b + 30.0 * c) * t * t + (-12.0 * b - 48.0 * c) * t + (8.0 * b + 24.0 * c)) / 6.0
+        }
+      },
+      FilterKind::Lanczos => Self::sinc(x) * Self::sinc(x / r),
+      FilterKind::BlackmanHarris => {
+        // 窗口定义在 [-r, r] 上，t 为其中的相对位置。
+        let t = (x + r) / (2.0 * r);
+        let phase = 2.0 * rtweekend::PI * t;
+        0.35875 - 0.48829 * phase.cos() + 0.14128 * (2.0 * phase).cos() - 0.01168 * (3.0 * phase).cos()
+      },
+    }
+  }
+
+  fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+      return 1.0;
+    }
+    let px = rtweekend::PI * x;
+    px.sin() / px
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn filters_integrate_to_one() {
+    for name in FILTERS {
+      for radius in [None, Some(0.75), Some(2.5)] {
+        let filter = Filter::new(name, radius).unwrap();
+        let steps = 400;
+        let dx = 2.0 * filter.radius / steps as f64;
+        let mut integral = 0.0;
+        for j in 0..steps {
+          for i in 0..steps {
+            let x = -filter.radius + (i as f64 + 0.5) * dx;
+            let y = -filter.radius + (j as f64 + 0.5) * dx;
+            integral += filter.evaluate(x, y) * dx * dx;
+          }
+        }
+        assert!((integral - 1.0).abs() < 1e-3, "{} with radius {:?} integrates to {}", name, radius, integral);
+      }
+    }
+  }
+
+  #[test]
+  fn unknown_filter_is_rejected() {
+    assert!(Filter::new("sinc", None).is_none());
+  }
+}
@@ -0,0 +1,392 @@
+use std::io::{Read, Write};
+
+use super::color::Color;
+use super::vec3::Vec3;
+use super::exr::{self, ExrChannel, ExrPixels};
+use super::filter::Filter;
+
+#[derive(Clone, Copy, Default)]
+pub struct Aov {
+  pub albedo: Color,      // Reflectance of the first non-specular surface
+  pub normal: Vec3,       // Shading normal of the first non-specular surface
+  pub depth: f64,         // Distance to the first hit, zero for rays that miss
+  pub direct: Color,      // Light emitted towards the camera or reaching it after one non-specular bounce
+  pub indirect: Color,    // Light reaching the camera after two or more non-specular bounces
+  pub object_id: usize,   // Id of the first primitive hit, zero for rays that miss
+  pub material_id: usize, // Id of the material of the first primitive hit, zero for rays that miss
+}
+
+// write_sums 为每个像素写出的数值个数。
+const SUM_VALUES: usize = 19;
+
+// read_sums 接受的最大图像边长，超出的尺寸视为损坏的检查点。
+const MAX_DIMENSION: usize = 1 << 16;
+
+pub struct Framebuffer {
+  width: usize,
+  height: usize,
+  pixels: Vec<Color>,
+  aovs: Vec<Aov>,
+  counts: Vec<usize>,
+  filter: Option<Filter>,
+  weights: Vec<f64>,
+}
+
+impl Framebuffer {
+  pub fn new(width: usize, height: usize) -> Self {
+    Self {
+      width,
+      height,
+      pixels: vec![Color::default(); width * height],
+      aovs: Vec::new(),
+      counts: Vec::new(),
+      filter: None,
+      weights: Vec::new(),
+    }
+  }
+
+  pub fn new_with_aovs(width: usize, height: usize) -> Self {
+    Self {
+      aovs: vec![Aov::default(); width * height],
+      ..Self::new(width, height)
+    }
+  }
+
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  pub fn has_aovs(&self) -> bool {
+    !self.aovs.is_empty()
+  }
+
+  pub fn enable_sample_counts(&mut self) {
+    // 记录每个像素各自的样本数，此后写出时按像素的样本数而不是 samples_per_pixel 求平均。
+    self.counts = vec![0; self.width * self.height];
+  }
+
+  pub fn sample_count(&self, i: usize, j: usize, samples_per_pixel: usize) -> usize {
+    // Returns the count of samples pixel i,j took, which is samples_per_pixel unless counts are enabled.
+    if self.counts.is_empty() {
+      samples_per_pixel
+    } else {
+      self.counts[j * self.width + i]
+    }
+  }
+
+  pub fn add_sample_count(&mut self, i: usize, j: usize, n: usize) {
+    self.counts[j * self.width + i] += n;
+  }
+
+  pub fn pixel(&self, i: usize, j: usize) -> Color {
+    self.pixels[j * self.width + i]
+  }
+
+  pub fn aov(&self, i: usize, j: usize) -> &Aov {
+    &self.aovs[j * self.width + i]
+  }
+
+  pub fn set(&mut self, i: usize, j: usize, c: Color) {
+    self.pixels[j * self.width + i] = c;
+  }
+
+  pub fn add(&mut self, i: usize, j: usize, c: Color) {
+    self.pixels[j * self.width + i] += c;
+  }
+
+  pub fn add_aov(&mut self, i: usize, j: usize, sample: &Aov) {
+    self.add_aov_weighted(i, j, sample, 1.0);
+  }
+
+  fn add_aov_weighted(&mut self, i: usize, j: usize, sample: &Aov, weight: f64) {
+    // 连续量按样本加权求和，标识无法求平均，因此保留像素中第一个击中物体的样本的标识。
+    let aov = &mut self.aovs[j * self.width + i];
+    aov.albedo += weight * sample.albedo;
+    aov.normal += weight * sample.normal;
+    aov.depth += weight * sample.depth;
+    aov.direct += weight * sample.direct;
+    aov.indirect += weight * sample.indirect;
+    if aov.object_id == 0 {
+      aov.object_id = sample.object_id;
+      aov.material_id = sample.material_id;
+    }
+  }
+
+  pub fn enable_filter(&mut self, filter: Filter) {
+    // 此后 add_filtered 按滤波器权重把样本累加到邻近的像素上，直到 resolve_filter 归一化为止。
+    // 半个像素宽的盒式滤波器与直接累加等价，因此不记录权重。
+    if !filter.is_pixel_box() {
+      self.filter = Some(filter);
+      self.weights = vec![0.0; self.width * self.height];
+    }
+  }
+
+  pub fn add_filtered(&mut self, i: usize, j: usize, position: (f64, f64), c: Color, aov: Option<&Aov>) {
+    // 添加像素 i,j 中位于连续光栅坐标 position 处的样本。
+    let Some(filter) = self.filter else {
+      self.add(i, j, c);
+      if let Some(aov) = aov {
+        self.add_aov(i, j, aov);
+      }
+      return;
+    };
+
+    // 中心与样本的距离不超过半径的像素都会收到样本。
+    let (x, y) = position;
+    let r = filter.radius;
+    let range = |v: f64, n: usize| {
+      let first = (v - r - 0.5).ceil().max(0.0) as usize;
+      let last = ((v + r - 0.5).floor().min(n as f64 - 1.0)).max(-1.0);
+      first..(last + 1.0) as usize
+    };
+    for pj in range(y, self.height) {
+      for pi in range(x, self.width) {
+        let weight = filter.evaluate(pi as f64 + 0.5 - x, pj as f64 + 0.5 - y);
+        if weight == 0.0 {
+          continue;
+        }
+        self.weights[pj * self.width + pi] += weight;
+        self.add(pi, pj, weight * c);
+        if let Some(aov) = aov {
+          self.add_aov_weighted(pi, pj, aov, weight);
+        }
+      }
+    }
+  }
+
+  pub fn resolve_filter(&mut self, samples_per_pixel: usize) {
+    // 将每个像素的加权和换算为样本数乘以加权平均，此后帧缓冲与直接累加样本的帧缓冲一样使用。
+    if self.filter.take().is_none() {
+      return;
+    }
+
+    let weights = std::mem::take(&mut self.weights);
+    for j in 0..self.height {
+      for i in 0..self.width {
+        let p = j * self.width + i;
+        let w = weights[p];
+        if w.abs() <= 1e-12 {
+          // 没有收到样本的像素（例如只由 splat 累加贡献的积分器）保持原样。
+          continue;
+        }
+        let scale = self.sample_count(i, j, samples_per_pixel) as f64 / w;
+        self.pixels[p] = scale * self.pixels[p];
+        if let Some(aov) = self.aovs.get_mut(p) {
+          aov.albedo = scale * aov.albedo;
+          aov.normal = scale * aov.normal;
+          aov.depth *= scale;
+          aov.direct = scale * aov.direct;
+          aov.indirect = scale * aov.indirect;
+        }
+      }
+    }
+  }
+
+  pub fn splat(&mut self, x: f64, y: f64, c: Color) {
+    // 将贡献累加到连续光栅坐标 x,y 所在的像素上，落在图像之外的贡献被丢弃。
+    if x < 0.0 || y < 0.0 {
+      return;
+    }
+    let (i, j) = (x as usize, y as usize);
+    if i < self.width && j < self.height {
+      self.add(i, j, c);
+    }
+  }
+
+  pub fn write_ppm(&self, out: &mut dyn Write, samples_per_pixel: usize) -> std::io::Result<()> {
+    writeln!(out, "P3\n{} {}\n255", self.width, self.height)?;
+    for j in 0..self.height {
+      for i in 0..self.width {
+        self.pixel(i, j).write_color(out, self.sample_count(i, j, samples_per_pixel))?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn to_rgb8(&self, samples_per_pixel: usize) -> Vec<u8> {
+    // Returns the gamma corrected image as row-major 8-bit RGB triples, like write_ppm writes it.
+    let mut rgb = Vec::with_capacity(3 * self.pixels.len());
+    for j in 0..self.height {
+      for i in 0..self.width {
+        rgb.extend_from_slice(&self.pixel(i, j).to_bytes(self.sample_count(i, j, samples_per_pixel)));
+      }
+    }
+    rgb
+  }
+
+  pub fn write_exr(&self, out: &mut dyn Write, samples_per_pixel: usize) -> std::io::Result<()> {
+    // 以线性浮点值写出多层 EXR：R,G,B 为最终图像，Z 为深度，其余 AOV 各占一层。
+    let component = |values: &mut Vec<Vec<f32>>, c: Vec3, scale: f64| {
+      for (a, channel) in values.iter_mut().enumerate() {
+        channel.push((c[a] * scale) as f32);
+      }
+    };
+
+    let mut beauty = vec![Vec::new(); 3];
+    let mut albedo = vec![Vec::new(); 3];
+    let mut normal = vec![Vec::new(); 3];
+    let mut direct = vec![Vec::new(); 3];
+    let mut indirect = vec![Vec::new(); 3];
+    let mut depth = Vec::new();
+    let mut object_id = Vec::new();
+    let mut material_id = Vec::new();
+    for j in 0..self.height {
+      for i in 0..self.width {
+        let scale = 1.0 / self.sample_count(i, j, samples_per_pixel) as f64;
+        component(&mut beauty, self.pixel(i, j), scale);
+        if !self.has_aovs() {
+          continue;
+        }
+
+        let aov = self.aov(i, j);
+        component(&mut albedo, aov.albedo, scale);
+        component(&mut normal, aov.normal, scale);
+        component(&mut direct, aov.direct, scale);
+        component(&mut indirect, aov.indirect, scale);
+        depth.push((aov.depth * scale) as f32);
+        object_id.push(aov.object_id as u32);
+        material_id.push(aov.material_id as u32);
+      }
+    }
+
+    let mut channels = Vec::new();
+    for (a, name) in ["R", "G", "B"].iter().enumerate() {
+      channels.push(ExrChannel { name: name.to_string(), pixels: ExrPixels::Float(&beauty[a]) });
+    }
+    if self.has_aovs() {
+      for (a, name) in ["R", "G", "B"].iter().enumerate() {
+        channels.push(ExrChannel { name: format!("albedo.{}", name), pixels: ExrPixels::Float(&albedo[a]) });
+        channels.push(ExrChannel { name: format!("direct.{}", name), pixels: ExrPixels::Float(&direct[a]) });
+        channels.push(ExrChannel { name: format!("indirect.{}", name), pixels: ExrPixels::Float(&indirect[a]) });
+      }
+      for (a, name) in ["X", "Y", "Z"].iter().enumerate() {
+        channels.push(ExrChannel { name: format!("normal.{}", name), pixels: ExrPixels::Float(&normal[a]) });
+      }
+      channels.push(ExrChannel { name: String::from("Z"), pixels: ExrPixels::Float(&depth) });
+      channels.push(ExrChannel { name: String::from("objectId"), pixels: ExrPixels::Uint(&object_id) });
+      channels.push(ExrChannel { name: String::from("materialId"), pixels: ExrPixels::Uint(&material_id) });
+    }
+
+    exr::write_exr(out, self.width, self.height, &mut channels)
+  }
+
+  pub fn write_sums(&self, out: &mut dyn Write) -> std::io::Result<()> {
+    // 以小端字节序原样写出每个像素的累加值、AOV 和样本数，read_sums 可以逐位恢复它们。
+    let mut bytes = Vec::new();
+    let mut put = |v: f64| bytes.extend_from_slice(&v.to_le_bytes());
+    for (p, c) in self.pixels.iter().enumerate() {
+      let aov = self.aovs.get(p).copied().unwrap_or_default();
+      for v in [*c, aov.albedo, aov.normal, aov.direct, aov.indirect] {
+        put(v.x());
+        put(v.y());
+        put(v.z());
+      }
+      put(aov.depth);
+      put(aov.object_id as f64);
+      put(aov.material_id as f64);
+      put(self.counts.get(p).copied().unwrap_or_default() as f64);
+    }
+    out.write_all(&bytes)
+  }
+
+  pub fn read_sums(input: &mut dyn Read, width: usize, height: usize) -> std::io::Result<Self> {
+    // 读出的帧缓冲总是带有 AOV 和逐像素样本数。尺寸来自不可信的文件，先检查尺寸，
+    // 再逐行读入数据，最后才分配帧缓冲，截断的文件因此在分配与文件大小不相称的内存之前就会报错。
+    let row_bytes = width.checked_mul(SUM_VALUES * 8).filter(|_| width <= MAX_DIMENSION && height <= MAX_DIMENSION);
+    let Some(row_bytes) = row_bytes.filter(|&n| n.checked_mul(height).is_some()) else {
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid image size {}x{}", width, height)));
+    };
+
+    let mut bytes = vec![0; row_bytes];
+    let mut values: Vec<f64> = Vec::new();
+    for _ in 0..height {
+      input.read_exact(&mut bytes)?;
+      values.extend(bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())));
+    }
+
+    let mut film = Self::new_with_aovs(width, height);
+    film.enable_sample_counts();
+    for (p, v) in values.chunks_exact(SUM_VALUES).enumerate() {
+      let vec3 = |k: usize| Vec3::new(v[k], v[k + 1], v[k + 2]);
+      film.pixels[p] = vec3(0);
+      let aov = &mut film.aovs[p];
+      aov.albedo = vec3(3);
+      aov.normal = vec3(6);
+      aov.direct = vec3(9);
+      aov.indirect = vec3(12);
+      aov.depth = v[15];
+      aov.object_id = v[16] as usize;
+      aov.material_id = v[17] as usize;
+      film.counts[p] = v[18] as usize;
+    }
+    Ok(film)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sums_round_trip() {
+    let mut film = Framebuffer::new_with_aovs(3, 2);
+    film.enable_sample_counts();
+    for j in 0..2 {
+      for i in 0..3 {
+        let k = (j * 3 + i) as f64;
+        film.set(i, j, Color::new(k, 0.1 + k, -1.0 / (k + 3.0)));
+        film.add_aov(i, j, &Aov {
+          albedo: Color::new(0.3, 0.2 * k, 0.1),
+          normal: Vec3::new(0.0, 1.0, k),
+          depth: 1.0 / (k + 7.0),
+          direct: Color::new(k, k, k),
+          indirect: Color::new(1e-300, 1e300, k),
+          object_id: j * 3 + i,
+          material_id: 42 + i,
+        });
+        film.add_sample_count(i, j, 5 + i);
+      }
+    }
+
+    let mut bytes = Vec::new();
+    film.write_sums(&mut bytes).unwrap();
+    let read = Framebuffer::read_sums(&mut bytes.as_slice(), 3, 2).unwrap();
+
+    let bits = |c: Color| [c.x().to_bits(), c.y().to_bits(), c.z().to_bits()];
+    for j in 0..2 {
+      for i in 0..3 {
+        let (a, b) = (film.aov(i, j), read.aov(i, j));
+        assert_eq!(bits(film.pixel(i, j)), bits(read.pixel(i, j)));
+        assert_eq!(bits(a.albedo), bits(b.albedo));
+        assert_eq!(bits(a.normal), bits(b.normal));
+        assert_eq!(a.depth.to_bits(), b.depth.to_bits());
+        assert_eq!(bits(a.direct), bits(b.direct));
+        assert_eq!(bits(a.indirect), bits(b.indirect));
+        assert_eq!((a.object_id, a.material_id), (b.object_id, b.material_id));
+        assert_eq!(film.sample_count(i, j, 0), read.sample_count(i, j, 0));
+      }
+    }
+  }
+
+  #[test]
+  fn read_sums_rejects_truncated_input() {
+    let mut bytes = Vec::new();
+    Framebuffer::new(2, 2).write_sums(&mut bytes).unwrap();
+    bytes.pop();
+    assert!(Framebuffer::read_sums(&mut bytes.as_slice(), 2, 2).is_err());
+  }
+
+  #[test]
+  fn read_sums_rejects_corrupt_sizes() {
+    let read = |width, height| Framebuffer::read_sums(&mut [0_u8; 64].as_slice(), width, height).err().unwrap().kind();
+    for (width, height) in [(usize::MAX, 2), (2, usize::MAX), (MAX_DIMENSION + 1, 1)] {
+      assert_eq!(read(width, height), std::io::ErrorKind::InvalidData);
+    }
+    // 尺寸合法但数据不足时在分配帧缓冲之前报错。
+    assert_eq!(read(MAX_DIMENSION, MAX_DIMENSION), std::io::ErrorKind::UnexpectedEof);
+  }
+}
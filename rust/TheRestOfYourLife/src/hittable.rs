@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::rc::Rc;
 
 use super::rtweekend;
@@ -16,6 +17,21 @@ pub struct HitRecord {
   pub u: f64,
   pub v: f64,
   pub front_face: bool,
+  pub object_id: usize,
+  pub material_id: usize,
+}
+
+thread_local! {
+  static NEXT_OBJECT_ID: Cell<usize> = const { Cell::new(1) };
+}
+
+pub fn next_object_id() -> usize {
+  // Returns a new primitive id. Ids follow construction order, so every process building the same scene agrees on them.
+  NEXT_OBJECT_ID.with(|id| {
+    let next = id.get();
+    id.set(next + 1);
+    next
+  })
 }
 
 pub trait Hittable {
@@ -27,6 +43,12 @@ pub trait Hittable {
   fn random(&self, _origin: Point3) -> Vec3 {
     Vec3::new(1.0, 0.0, 0.0)
   }
+  fn random_on_surface(&self, _rec: &mut HitRecord, _time: f64) -> f64 {
+    0.0
+  }
+  fn surface_pdf_value(&self, _p: Point3, _time: f64) -> f64 {
+    0.0
+  }
 }
 
 impl HitRecord {
@@ -21,9 +21,10 @@ pub struct HittableList {
 
 impl HittableList {
   pub fn new(object: Rc<dyn Hittable>) -> Self {
+    let bbox = object.bounding_box().clone();
     Self {
       objects: vec![object],
-      bbox: Aabb::default(),
+      bbox,
     }
   }
 
@@ -73,4 +74,21 @@ impl Hittable for HittableList {
     let int_size = self.objects.len() as i32;
     self.objects[rtweekend::random_int(0, int_size - 1) as usize].random(origin)
   }
+
+  fn random_on_surface(&self, rec: &mut HitRecord, time: f64) -> f64 {
+    let int_size = self.objects.len() as i32;
+    self.objects[rtweekend::random_int(0, int_size - 1) as usize].random_on_surface(rec, time);
+    self.surface_pdf_value(rec.p, time)
+  }
+
+  fn surface_pdf_value(&self, p: Point3, time: f64) -> f64 {
+    let weight = 1.0 / self.objects.len() as f64;
+    let mut sum = 0.0;
+
+    for object in self.objects.iter() {
+      sum += weight * object.surface_pdf_value(p, time);
+    }
+
+    sum
+  }
 }
\ No newline at end of file
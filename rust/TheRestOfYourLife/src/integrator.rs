@@ -0,0 +1,30 @@
+use super::camera::Camera;
+use super::color::Color;
+use super::hittable::Hittable;
+use super::framebuffer::Framebuffer;
+use super::ray::Ray;
+use super::progress;
+
+pub trait Integrator {
+  // 将每个像素的样本总和累加到 film 中，调用前相机必须已经初始化。
+  fn render(&self, cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable, film: &mut Framebuffer);
+}
+
+pub fn render_per_ray<F: FnMut(&Ray) -> Color>(cam: &Camera, film: &mut Framebuffer, mut li: F) {
+  // 对每个像素的每个分层样本生成相机光线，并把 li 的结果累加到对应像素。
+  let sqrt_spp = cam.sqrt_spp();
+  progress::start("scanlines", cam.film_height(), 0);
+  for j in 0..cam.film_height() {
+    for i in 0..cam.film_width() {
+      for s_j in 0..sqrt_spp {
+        for s_i in 0..sqrt_spp {
+          let sample = cam.get_ray_sample(i as i32, j as i32, s_i as i32, s_j as i32);
+          let color = if sample.weight > 0.0 { sample.weight * li(&sample.ray) } else { Color::default() };
+          film.add_filtered(i, j, sample.position, color, None);
+        }
+      }
+    }
+    progress::advance(j + 1);
+  }
+  progress::finish();
+}
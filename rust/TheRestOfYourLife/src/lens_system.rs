@@ -0,0 +1,357 @@
+use std::rc::Rc;
+
+use super::camera_model::{CameraModel, CameraRay};
+use super::ray::Ray;
+use super::vec3::{self, Point3, Vec3};
+
+// 真实镜头：光线从胶片出发，依次穿过镜头处方中的每个球面镜片和光阑，由此自然得到渐晕、畸变和对焦时的呼吸效应。
+// 镜头空间以毫米为单位，胶片中心在原点，镜头沿 -z 方向排列，与相机空间的朝向一致。
+// 镜头处方是文本表格，每行一个界面，从最靠近场景的界面写到最靠近胶片的界面：
+//   曲率半径  到下一个界面的厚度  界面后介质的折射率  通光孔径直径
+// 曲率半径为正表示球心在界面之后（朝向胶片），为零表示光阑；折射率为零等同于空气；
+// 最后一行的厚度是到胶片的距离，对焦时会被重新计算。# 之后的内容为注释。
+
+#[derive(Clone)]
+pub struct LensElement {
+  pub curvature_radius: f64, // Radius of the spherical interface in mm, zero for the aperture stop
+  pub thickness: f64,        // Distance along the axis to the next interface, or to the film for the last one
+  pub ior: f64,              // Index of refraction of the medium behind the interface
+  pub aperture_radius: f64,  // Radius of the clear aperture in mm
+}
+
+#[derive(Clone)]
+pub struct LensSystem {
+  pub elements: Vec<LensElement>, // Interfaces from the one nearest the scene to the one nearest the film
+  pub film_diagonal: f64,         // Diagonal of the film in mm
+  pub mm_per_unit: f64,           // Millimetres in one world unit
+}
+
+impl std::str::FromStr for LensSystem {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut elements = Vec::new();
+    for (number, line) in s.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or_default().trim();
+      if line.is_empty() {
+        continue;
+      }
+      let values: Vec<f64> = line
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid number on line {}", number + 1))?;
+      let [curvature_radius, thickness, ior, aperture] = values[..] else {
+        return Err(format!("expected radius, thickness, IOR and aperture on line {}", number + 1));
+      };
+      if thickness < 0.0 || ior < 0.0 || aperture <= 0.0 {
+        return Err(format!("negative thickness or IOR, or empty aperture on line {}", number + 1));
+      }
+      elements.push(LensElement {
+        curvature_radius,
+        thickness,
+        ior: if ior == 0.0 { 1.0 } else { ior },
+        aperture_radius: aperture / 2.0,
+      });
+    }
+    if elements.is_empty() {
+      return Err(String::from("no lens elements"));
+    }
+
+    Ok(Self {
+      elements,
+      film_diagonal: 35.0,
+      mm_per_unit: 1.0,
+    })
+  }
+}
+
+impl LensSystem {
+  fn rear_z(&self) -> f64 {
+    // Returns the distance from the film to the interface nearest to it.
+    self.elements.last().map_or(0.0, |e| e.thickness)
+  }
+
+  fn front_z(&self) -> f64 {
+    // Returns the distance from the film to the interface nearest to the scene.
+    self.elements.iter().map(|e| e.thickness).sum()
+  }
+
+  pub fn trace_from_film(&self, r: &Ray) -> Option<Ray> {
+    // 从胶片一侧向场景追踪光线，被镜片边缘或光阑挡住、或发生全反射时返回 None。
+    let mut ray = Ray::new(r.origin(), r.direction());
+    let mut element_z = 0.0;
+    for (i, element) in self.elements.iter().enumerate().rev() {
+      element_z -= element.thickness;
+      let eta_t = if i > 0 { self.elements[i - 1].ior } else { 1.0 };
+      ray = Self::trace_interface(element, element_z, &ray, element.ior / eta_t)?;
+    }
+    Some(ray)
+  }
+
+  fn trace_from_scene(&self, r: &Ray) -> Option<Ray> {
+    // 从场景一侧向胶片追踪光线，只用于求镜头的基点。
+    let mut ray = Ray::new(r.origin(), r.direction());
+    let mut element_z = -self.front_z();
+    for (i, element) in self.elements.iter().enumerate() {
+      let eta_i = if i > 0 { self.elements[i - 1].ior } else { 1.0 };
+      ray = Self::trace_interface(element, element_z, &ray, eta_i / element.ior)?;
+      element_z += element.thickness;
+    }
+    Some(ray)
+  }
+
+  fn trace_interface(element: &LensElement, element_z: f64, r: &Ray, eta: f64) -> Option<Ray> {
+    // 求光线与顶点位于 element_z 的界面的交点，并按相对折射率 eta 折射。
+    let d = r.direction();
+    if element.curvature_radius == 0.0 {
+      // 光阑是垂直于光轴的平面，只遮挡不折射。
+      if d.z() == 0.0 {
+        return None;
+      }
+      let t = (element_z - r.origin().z()) / d.z();
+      let p = r.at(t);
+      if t <= 0.0 || p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+        return None;
+      }
+      return Some(Ray::new(p, d));
+    }
+
+    let (t, normal) = Self::intersect_spherical(element.curvature_radius, element_z + element.curvature_radius, r)?;
+    let p = r.at(t);
+    if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius {
+      return None;
+    }
+    let wt = Self::refract(vec3::unit_vector(-d), normal, eta)?;
+    Some(Ray::new(p, wt))
+  }
+
+  fn intersect_spherical(radius: f64, z_center: f64, r: &Ray) -> Option<(f64, Vec3)> {
+    // Returns the distance to the hit on a spherical interface and its normal facing the ray.
+    let o = r.origin() - Vec3::new(0.0, 0.0, z_center);
+    let d = r.direction();
+    let a = d.length_squared();
+    let b = 2.0 * vec3::dot(d, o);
+    let c = o.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+      return None;
+    }
+
+    // 界面只是球面的一部分：凸向光线来向时取较近的交点，否则取较远的交点。
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-b - sqrtd) / (2.0 * a), (-b + sqrtd) / (2.0 * a));
+    let use_closer = (d.z() > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+      return None;
+    }
+
+    let normal = vec3::unit_vector(o + t * d);
+    Some((t, if vec3::dot(normal, d) > 0.0 { -normal } else { normal }))
+  }
+
+  fn refract(wi: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    // Returns the refraction of unit direction wi about n for the relative IOR eta, None on total internal reflection.
+    let cos_theta_i = vec3::dot(n, wi);
+    let sin2_theta_t = eta * eta * (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    if sin2_theta_t >= 1.0 {
+      return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(eta * -wi + (eta * cos_theta_i - cos_theta_t) * n)
+  }
+
+  fn cardinal_points(x: f64, r_out: &Ray) -> (f64, f64) {
+    // 平行于光轴、高度为 x 的光线穿过镜头后成为 r_out，返回主平面和焦点的 z 坐标。
+    let (o, d) = (r_out.origin(), r_out.direction());
+    let principal = o.z() + (x - o.x()) / d.x() * d.z();
+    let focal = o.z() - o.x() / d.x() * d.z();
+    (principal, focal)
+  }
+
+  pub fn focused(&self, distance: f64) -> Option<LensSystem> {
+    // 返回对焦到距胶片 distance 毫米处的镜头：用厚透镜近似求出镜头组需要移动的距离，调整最后一个界面到胶片的厚度。
+    // 物体比最近对焦距离还近时返回 None。
+    let x = 0.001 * self.film_diagonal;
+    let from_scene = self.trace_from_scene(&Ray::new(Point3::new(x, 0.0, -self.front_z() - 1.0), Vec3::new(0.0, 0.0, 1.0)))?;
+    let from_film = self.trace_from_film(&Ray::new(Point3::new(x, 0.0, -self.rear_z() + 1.0), Vec3::new(0.0, 0.0, -1.0)))?;
+    let (image_principal, image_focal) = Self::cardinal_points(x, &from_scene);
+    let (object_principal, _) = Self::cardinal_points(x, &from_film);
+    let f = image_focal - image_principal;
+
+    // 镜头组远离胶片移动 delta 后，物距 s_o 与像距 s_i 满足 1/s_o + 1/s_i = 1/f，整理为 delta 的二次方程。
+    let a = object_principal + distance;
+    let c = (a - image_principal) * (a - image_principal - 4.0 * f);
+    if f <= 0.0 || c < 0.0 {
+      return None;
+    }
+    let delta = 0.5 * (a + image_principal - c.sqrt());
+
+    let mut focused = self.clone();
+    let rear = focused.elements.last_mut()?;
+    rear.thickness += delta;
+    (rear.thickness > 0.0).then_some(focused)
+  }
+
+  pub fn model(&self, aspect: f64, focus_dist: f64) -> Option<Rc<dyn CameraModel>> {
+    // 创建对焦到 focus_dist 个世界单位处的相机模型，无法对焦时返回 None。
+    let system = self.focused(focus_dist * self.mm_per_unit)?;
+    Some(Rc::new(RealisticLens::new(system, aspect)))
+  }
+}
+
+pub struct RealisticLens {
+  system: LensSystem,            // Lens focused at the distance the camera focuses at
+  film_width: f64,               // Width of the film in mm
+  film_height: f64,              // Height of the film in mm
+  pupil_bounds: Vec<[f64; 4]>,   // Exit pupil bounds x0, x1, y0, y1 on the rear element by film radius, for film points on +x
+  normalization: f64,            // Reciprocal of the irradiance weight at the film center
+}
+
+impl RealisticLens {
+  const PUPIL_INTERVALS: usize = 64;
+  const PUPIL_GRID: usize = 48;
+
+  pub fn new(system: LensSystem, aspect: f64) -> Self {
+    let film_height = system.film_diagonal / (1.0 + aspect * aspect).sqrt();
+    let mut lens = Self {
+      system,
+      film_width: film_height * aspect,
+      film_height,
+      pupil_bounds: Vec::new(),
+      normalization: 0.0,
+    };
+
+    // 出瞳范围：对胶片中心到角落的每一段半径，找出后镜片上能让光线穿过整个镜头的区域。
+    // 只在这个区域内采样透镜点，避免大部分光线被光阑挡住。
+    let half_diagonal = lens.system.film_diagonal / 2.0;
+    lens.pupil_bounds = (0..Self::PUPIL_INTERVALS)
+      .map(|k| {
+        let r0 = k as f64 / Self::PUPIL_INTERVALS as f64 * half_diagonal;
+        let r1 = (k + 1) as f64 / Self::PUPIL_INTERVALS as f64 * half_diagonal;
+        lens.bound_exit_pupil(r0, r1)
+      })
+      .collect();
+
+    // 以胶片中心的辐照度为基准，使画面中心的亮度与理想透镜一致，边缘的渐晕则保留下来。
+    let center_weight = lens.irradiance_weight(0.0);
+    lens.normalization = if center_weight > 0.0 { 1.0 / center_weight } else { 0.0 };
+    lens
+  }
+
+  fn bound_exit_pupil(&self, r0: f64, r1: f64) -> [f64; 4] {
+    // Returns the bounds on the rear element of the points through which rays from film radii r0..r1 on +x leave the lens.
+    let rear_radius = 1.5 * self.system.elements.last().map_or(0.0, |e| e.aperture_radius);
+    let rear_z = self.system.rear_z();
+    let step = 2.0 * rear_radius / Self::PUPIL_GRID as f64;
+    let mut bounds = [f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY];
+
+    for film_step in 0..4 {
+      let film_x = r0 + (r1 - r0) * film_step as f64 / 3.0;
+      for gy in 0..Self::PUPIL_GRID {
+        for gx in 0..Self::PUPIL_GRID {
+          let x = -rear_radius + (gx as f64 + 0.5) * step;
+          let y = -rear_radius + (gy as f64 + 0.5) * step;
+          let film_point = Point3::new(film_x, 0.0, 0.0);
+          let r = Ray::new(film_point, Point3::new(x, y, -rear_z) - film_point);
+          if self.system.trace_from_film(&r).is_some() {
+            bounds = [bounds[0].min(x), bounds[1].max(x), bounds[2].min(y), bounds[3].max(y)];
+          }
+        }
+      }
+    }
+
+    // 网格点之间的区域也可能透光，向外扩大一个网格间距。
+    if bounds[0] > bounds[1] {
+      return [0.0; 4];
+    }
+    [bounds[0] - step, bounds[1] + step, bounds[2] - step, bounds[3] + step]
+  }
+
+  fn irradiance_weight(&self, film_x: f64) -> f64 {
+    // Returns the average sample weight before normalization at film point film_x on +x.
+    let n = Self::PUPIL_GRID;
+    let mut sum = 0.0;
+    for gy in 0..n {
+      for gx in 0..n {
+        let lens = ((gx as f64 + 0.5) / n as f64, (gy as f64 + 0.5) / n as f64);
+        sum += self.trace(Point3::new(film_x, 0.0, 0.0), lens).map_or(0.0, |(_, weight)| weight);
+      }
+    }
+    sum / (n * n) as f64
+  }
+
+  fn trace(&self, film_point: Point3, lens: (f64, f64)) -> Option<(Ray, f64)> {
+    // 在胶片点所在半径的出瞳范围内采样后镜片上的点，追踪穿过镜头的光线，返回镜头空间的光线和未归一化的权重。
+    let r = (film_point.x() * film_point.x() + film_point.y() * film_point.y()).sqrt();
+    let half_diagonal = self.system.film_diagonal / 2.0;
+    let k = ((r / half_diagonal * Self::PUPIL_INTERVALS as f64) as usize).min(Self::PUPIL_INTERVALS - 1);
+    let [x0, x1, y0, y1] = self.pupil_bounds[k];
+    let area = (x1 - x0) * (y1 - y0);
+    if area <= 0.0 {
+      return None;
+    }
+
+    // 出瞳范围是对 +x 轴上的胶片点求的，按胶片点的方位角旋转到实际位置。
+    let (px, py) = (x0 + lens.0 * (x1 - x0), y0 + lens.1 * (y1 - y0));
+    let (sin_phi, cos_phi) = if r > 0.0 { (film_point.y() / r, film_point.x() / r) } else { (0.0, 1.0) };
+    let rear_point = Point3::new(cos_phi * px - sin_phi * py, sin_phi * px + cos_phi * py, -self.system.rear_z());
+
+    let direction = rear_point - film_point;
+    let ray = self.system.trace_from_film(&Ray::new(film_point, direction))?;
+
+    // 胶片上的辐照度为 L cos^4(theta) / z^2 在后镜片上的积分，z 对所有样本相同，并入归一化系数。
+    let cos_theta = direction.z().abs() / direction.length();
+    Some((ray, cos_theta.powi(4) * area))
+  }
+}
+
+impl CameraModel for RealisticLens {
+  fn generate_ray(&self, film: (f64, f64), lens: (f64, f64), time: f64) -> CameraRay {
+    // 镜头成倒像：图像左上角对应胶片上 +x、-y 的位置。
+    let film_point = Point3::new((0.5 - film.0) * self.film_width, (film.1 - 0.5) * self.film_height, 0.0);
+    match self.trace(film_point, lens) {
+      Some((ray, weight)) => CameraRay {
+        ray: Ray::new_with_time(ray.origin() / self.system.mm_per_unit, ray.direction(), time),
+        weight: weight * self.normalization,
+      },
+      None => CameraRay {
+        ray: Ray::new_with_time(Point3::default(), Vec3::new(0.0, 0.0, -1.0), time),
+        weight: 0.0,
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_prescription() {
+    let lens: LensSystem = include_str!("../lenses/dgauss50.txt").parse().unwrap();
+    assert_eq!(lens.elements.len(), 11);
+    assert_eq!(lens.elements[5].ior, 1.0);
+    assert_eq!(lens.elements[5].aperture_radius, 8.55);
+  }
+
+  #[test]
+  fn rejects_invalid_prescriptions() {
+    let error = |s: &str| s.parse::<LensSystem>().err().unwrap();
+    assert_eq!(error(""), "no lens elements");
+    assert_eq!(error("# only a comment\n"), "no lens elements");
+    assert_eq!(error("29.475 3.76 1.67 25.2\n84.83 0.12 x 25.2"), "invalid number on line 2");
+    assert_eq!(error("29.475 3.76 1.67"), "expected radius, thickness, IOR and aperture on line 1");
+    assert_eq!(error("29.475 -3.76 1.67 25.2"), "negative thickness or IOR, or empty aperture on line 1");
+    assert_eq!(error("29.475 3.76 1.67 0"), "negative thickness or IOR, or empty aperture on line 1");
+  }
+
+  #[test]
+  fn cannot_focus_closer_than_the_minimum_distance() {
+    let lens: LensSystem = include_str!("../lenses/dgauss50.txt").parse().unwrap();
+    assert!(lens.focused(1000.0).is_some());
+    assert!(lens.focused(10.0).is_none());
+    assert!(lens.model(1.0, 10.0).is_none());
+  }
+}
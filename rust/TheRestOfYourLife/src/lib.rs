@@ -16,4 +16,25 @@ pub mod perlin;
 pub mod quad;
 pub mod constant_medium;
 pub mod onb;
-pub mod pdf;
\ No newline at end of file
+pub mod pdf;
+pub mod framebuffer;
+pub mod integrator;
+pub mod bdpt;
+pub mod photon_map;
+pub mod sppm;
+pub mod pssmlt;
+pub mod visualize;
+pub mod exr;
+pub mod denoise;
+pub mod progressive;
+pub mod distributed;
+pub mod png;
+pub mod preview;
+pub mod stats;
+pub mod progress;
+pub mod filter;
+pub mod camera_model;
+pub mod lens_system;
+pub mod aperture;
+pub mod exposure;
+pub mod animation;
\ No newline at end of file
@@ -17,6 +17,27 @@ pub mod quad;
 pub mod constant_medium;
 pub mod onb;
 pub mod pdf;
+pub mod framebuffer;
+pub mod integrator;
+pub mod bdpt;
+pub mod photon_map;
+pub mod sppm;
+pub mod pssmlt;
+pub mod visualize;
+pub mod exr;
+pub mod denoise;
+pub mod progressive;
+pub mod distributed;
+pub mod png;
+pub mod preview;
+pub mod stats;
+pub mod progress;
+pub mod filter;
+pub mod camera_model;
+pub mod lens_system;
+pub mod aperture;
+pub mod exposure;
+pub mod animation;
 
 use std::rc::Rc;
 
@@ -39,8 +60,573 @@ use hittable::{
   RotateY,
 };
 use sphere::Sphere;
+use bdpt::Bdpt;
+use sppm::Sppm;
+use pssmlt::Pssmlt;
+use visualize::{
+  DebugView,
+  DebugIntegrator,
+  AmbientOcclusion,
+};
+use camera::{AdaptiveSampling, CropWindow};
+use lens_system::LensSystem;
+use exposure::{Exposure, Shutter, ShutterMode};
+use animation::CameraAnimation;
+use aperture::{Aperture, ApertureImage, ApertureShape, APERTURES};
+use rtw_stb_image::RtwImage;
+use camera_model::{FisheyeMapping, Projection, Stereo, StereoLayout, StereoMode, TiltShift, PROJECTIONS};
+use progressive::{RenderState, WorkerShare};
+use framebuffer::Framebuffer;
+use hittable::Hittable;
+use bvh::BvhNode;
+use denoise::AtrousDenoiser;
+use preview::{PreviewServer, Progress};
+use progress::ProgressStyle;
+use filter::{Filter, FILTERS};
+
+const INTEGRATORS: [&str; 11] = [
+  "path", "bdpt", "sppm", "pssmlt",
+  "normals", "uv", "depth", "front-face", "material-id", "bvh-heatmap", "ao",
+];
+
+// 只由协调进程处理、不转发给工作进程的参数，每个都带一个取值。
+const COORDINATOR_ARGS: [&str; 9] = [
+  "--workers", "--worker", "--preview-port", "--checkpoint", "--checkpoint-interval", "--resume",
+  "--aov-output", "--sample-heatmap", "--stats-json",
+];
+
+struct Options {
+  integrator: String,                  // Light transport algorithm or debug view, one of INTEGRATORS
+  photons: usize,                      // Photons shot per SPPM iteration
+  photon_radius: f64,                  // Initial SPPM gather radius
+  ao_radius: f64,                      // Ambient occlusion radius
+  aov_output: Option<String>,          // Path of the multi-layer EXR holding the image and its AOVs
+  denoise: bool,                       // Filter the path traced image with the a-trous denoiser
+  denoise_iterations: usize,           // Count of a-trous filter passes
+  adaptive: bool,                      // Spend samples where pixels are noisy instead of uniformly
+  min_spp: usize,                      // Samples every pixel takes with adaptive sampling
+  max_spp: usize,                      // Most samples a pixel takes with adaptive sampling
+  target_error: f64,                   // Relative error at which adaptive sampling stops
+  sample_heatmap: Option<String>,      // Path of the PPM showing the samples taken by each pixel
+  progressive: bool,                   // Render in passes of one sample per pixel
+  seed: u64,                           // Seed of the progressive per-sample random sequences
+  checkpoint: Option<String>,          // Path the progressive render state is saved to
+  checkpoint_interval: f64,            // Seconds between checkpoints
+  resume: Option<String>,              // Path of a checkpoint to continue rendering from
+  workers: usize,                      // Count of worker processes a progressive render is split between
+  worker: Option<WorkerShare>,         // Tiles to render when running as a worker process
+  preview_port: Option<u16>,           // Local port the progressive render is previewed on over HTTP
+  progress: ProgressStyle,             // How progress is reported on stderr
+  russian_roulette: Option<usize>,     // Bounces after which the path tracer may end paths by Russian roulette
+  stats: bool,                         // Print render statistics to stderr when done
+  stats_json: Option<String>,          // Path of the JSON file render statistics are exported to
+  crop: Option<CropWindow>,            // Pixels of the image to render, the whole image if None
+  trace_pixel: Option<(usize, usize)>, // Pixel whose paths are traced alone with every bounce logged
+  filter: String,                      // Pixel reconstruction filter, one of FILTERS
+  filter_radius: Option<f64>,          // Filter radius in pixels, the filter's usual radius if None
+  clamp_indirect: Option<f64>,         // Largest luminance of a sample's indirect light
+  regularize: Option<f64>,             // Roughness given to specular vertices after a diffuse bounce
+  projection: String,                  // Camera projection, one of PROJECTIONS
+  view_height: Option<f64>,            // Orthographic viewport height in world units, matching vfov at lookat if None
+  fisheye_fov: f64,                    // Angle in degrees the fisheye image circle spans
+  stereo: Option<StereoMode>,          // Stereo rig rendering both eyes into one image, a single view if None
+  stereo_layout: StereoLayout,         // How the two eyes are arranged in the image
+  interocular: Option<f64>,            // Distance between the eyes, a thirtieth of the convergence distance if None
+  convergence: Option<f64>,            // Distance to the plane of zero parallax, the distance to lookat if None
+  lens: Option<String>,                // Path of the lens prescription traced instead of the projection
+  film_diagonal: f64,                  // Diagonal of the film behind the lens in mm
+  lens_scale: f64,                     // Millimetres in one world unit, for the lens prescription and the physical aperture
+  focus_distance: Option<f64>,         // Distance the camera focuses at, also in keyframes, the distance to lookat if None
+  defocus_angle: f64,                  // Angle in degrees the thin lens subtends from the plane of focus
+  aperture: String,                    // Shape of the thin lens opening, one of APERTURES
+  aperture_blades: usize,              // Count of blades of a polygonal aperture
+  aperture_rotation: f64,              // Rotation in degrees of a polygonal aperture
+  aperture_inner: f64,                 // Fraction of an annular aperture's radius that is blocked
+  aperture_image: Option<String>,      // Path of the grayscale image of an image aperture
+  cat_eye: f64,                        // Strength of the cat's-eye vignetting at the image corners
+  physical: bool,                      // Derive the field of view, defocus and brightness from exposure settings
+  exposure: Exposure,                  // Exposure settings used when physical is set, its mm_per_unit given by lens_scale
+  shutter: Shutter,                    // Times each row of the image is exposed
+  tilt_shift: TiltShift,               // Viewport shift and plane of focus tilt of the perspective projection
+  animation: Option<String>,           // Path of the camera keyframes, rendering a sequence of frames if set
+  frame_start: Option<i64>,            // First frame of the sequence, the first keyframe if None
+  frame_end: Option<i64>,              // Last frame of the sequence, the last keyframe if None
+  frame_output: String,                // Path of each frame's PPM, its run of # replaced by the zero-padded frame number
+}
+
+fn parse_args() -> Options {
+  let mut options = Options {
+    integrator: String::from("path"),
+    photons: Sppm::default().photons_per_iteration,
+    photon_radius: Sppm::default().initial_radius,
+    ao_radius: 100.0,
+    aov_output: None,
+    denoise: false,
+    denoise_iterations: AtrousDenoiser::default().iterations,
+    adaptive: false,
+    min_spp: 16,
+    max_spp: 1024,
+    target_error: 0.05,
+    sample_heatmap: None,
+    progressive: false,
+    seed: 0,
+    checkpoint: None,
+    checkpoint_interval: 60.0,
+    resume: None,
+    workers: 1,
+    worker: None,
+    preview_port: None,
+    progress: ProgressStyle::detect(),
+    russian_roulette: None,
+    stats: false,
+    stats_json: None,
+    crop: None,
+    trace_pixel: None,
+    filter: String::from("box"),
+    filter_radius: None,
+    clamp_indirect: None,
+    regularize: None,
+    projection: String::from("perspective"),
+    view_height: None,
+    fisheye_fov: 180.0,
+    stereo: None,
+    stereo_layout: StereoLayout::TopBottom,
+    interocular: None,
+    convergence: None,
+    lens: None,
+    film_diagonal: 35.0,
+    lens_scale: 1.0,
+    focus_distance: None,
+    defocus_angle: 0.0,
+    aperture: String::from("disk"),
+    aperture_blades: 6,
+    aperture_rotation: 0.0,
+    aperture_inner: 0.5,
+    aperture_image: None,
+    cat_eye: 0.0,
+    physical: false,
+    exposure: Exposure::default(),
+    shutter: Shutter::default(),
+    tilt_shift: TiltShift::default(),
+    animation: None,
+    frame_start: None,
+    frame_end: None,
+    frame_output: String::from("frame_####.ppm"),
+  };
+
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--integrator" => {
+        options.integrator = args.next().unwrap_or_default();
+        if !INTEGRATORS.contains(&options.integrator.as_str()) {
+          eprintln!("Unknown integrator \"{}\", expected one of {}.", options.integrator, INTEGRATORS.join(", "));
+          std::process::exit(1);
+        }
+      },
+      "--photons" => options.photons = parse_value(&arg, args.next()),
+      "--photon-radius" => options.photon_radius = parse_value(&arg, args.next()),
+      "--ao-radius" => options.ao_radius = parse_value(&arg, args.next()),
+      "--aov-output" => options.aov_output = Some(parse_value(&arg, args.next())),
+      "--denoise" => options.denoise = true,
+      "--denoise-iterations" => options.denoise_iterations = parse_value(&arg, args.next()),
+      "--adaptive" => options.adaptive = true,
+      "--min-spp" => options.min_spp = parse_value(&arg, args.next()),
+      "--max-spp" => options.max_spp = parse_value(&arg, args.next()),
+      "--target-error" => options.target_error = parse_value(&arg, args.next()),
+      "--sample-heatmap" => options.sample_heatmap = Some(parse_value(&arg, args.next())),
+      "--progressive" => options.progressive = true,
+      "--seed" => options.seed = parse_value(&arg, args.next()),
+      "--checkpoint" => options.checkpoint = Some(parse_value(&arg, args.next())),
+      "--checkpoint-interval" => options.checkpoint_interval = parse_value(&arg, args.next()),
+      "--resume" => options.resume = Some(parse_value(&arg, args.next())),
+      "--workers" => options.workers = parse_value(&arg, args.next()),
+      "--worker" => options.worker = Some(parse_value(&arg, args.next())),
+      "--preview-port" => options.preview_port = Some(parse_value(&arg, args.next())),
+      "--progress" => options.progress = parse_value(&arg, args.next()),
+      "--russian-roulette" => options.russian_roulette = Some(parse_value(&arg, args.next())),
+      "--stats" => options.stats = true,
+      "--stats-json" => options.stats_json = Some(parse_value(&arg, args.next())),
+      "--crop" => options.crop = Some(parse_value(&arg, args.next())),
+      "--filter" => {
+        options.filter = args.next().unwrap_or_default();
+        if !FILTERS.contains(&options.filter.as_str()) {
+          eprintln!("Unknown filter \"{}\", expected one of {}.", options.filter, FILTERS.join(", "));
+          std::process::exit(1);
+        }
+      },
+      "--projection" => {
+        options.projection = args.next().unwrap_or_default();
+        if !PROJECTIONS.contains(&options.projection.as_str()) {
+          eprintln!("Unknown projection \"{}\", expected one of {}.", options.projection, PROJECTIONS.join(", "));
+          std::process::exit(1);
+        }
+      },
+      "--view-height" => options.view_height = Some(parse_value(&arg, args.next())),
+      "--fisheye-fov" => options.fisheye_fov = parse_value(&arg, args.next()),
+      "--stereo" => options.stereo = Some(parse_value(&arg, args.next())),
+      "--stereo-layout" => options.stereo_layout = parse_value(&arg, args.next()),
+      "--interocular" => options.interocular = Some(parse_value(&arg, args.next())),
+      "--convergence" => options.convergence = Some(parse_value(&arg, args.next())),
+      "--lens" => options.lens = Some(parse_value(&arg, args.next())),
+      "--film-diagonal" => options.film_diagonal = parse_value(&arg, args.next()),
+      "--lens-scale" => options.lens_scale = parse_value(&arg, args.next()),
+      "--focus-distance" => options.focus_distance = Some(parse_value(&arg, args.next())),
+      "--defocus-angle" => options.defocus_angle = parse_value(&arg, args.next()),
+      "--aperture" => {
+        options.aperture = args.next().unwrap_or_default();
+        if !APERTURES.contains(&options.aperture.as_str()) {
+          eprintln!("Unknown aperture \"{}\", expected one of {}.", options.aperture, APERTURES.join(", "));
+          std::process::exit(1);
+        }
+      },
+      "--aperture-blades" => options.aperture_blades = parse_value(&arg, args.next()),
+      "--aperture-rotation" => options.aperture_rotation = parse_value(&arg, args.next()),
+      "--aperture-inner" => options.aperture_inner = parse_value(&arg, args.next()),
+      "--aperture-image" => options.aperture_image = Some(parse_value(&arg, args.next())),
+      "--cat-eye" => options.cat_eye = parse_value(&arg, args.next()),
+      "--physical" => options.physical = true,
+      "--f-number" => options.exposure.f_number = parse_value(&arg, args.next()),
+      "--focal-length" => options.exposure.focal_length = parse_value(&arg, args.next()),
+      "--sensor-width" => options.exposure.sensor_width = parse_value(&arg, args.next()),
+      "--shutter-open" => options.shutter.open = parse_value(&arg, args.next()),
+      "--shutter-close" => options.shutter.close = parse_value(&arg, args.next()),
+      "--rolling-shutter" => options.shutter.mode = ShutterMode::Rolling { readout: parse_value(&arg, args.next()) },
+      "--iso" => options.exposure.iso = parse_value(&arg, args.next()),
+      "--shift-x" => options.tilt_shift.shift.0 = parse_value(&arg, args.next()),
+      "--shift-y" => options.tilt_shift.shift.1 = parse_value(&arg, args.next()),
+      "--tilt-x" => options.tilt_shift.tilt.0 = parse_value(&arg, args.next()),
+      "--tilt-y" => options.tilt_shift.tilt.1 = parse_value(&arg, args.next()),
+      "--animation" => options.animation = Some(parse_value(&arg, args.next())),
+      "--frame-start" => options.frame_start = Some(parse_value(&arg, args.next())),
+      "--frame-end" => options.frame_end = Some(parse_value(&arg, args.next())),
+      "--frame-output" => options.frame_output = parse_value(&arg, args.next()),
+      "--filter-radius" => options.filter_radius = Some(parse_value(&arg, args.next())),
+      "--clamp-indirect" => options.clamp_indirect = Some(parse_value(&arg, args.next())),
+      "--regularize" => options.regularize = Some(parse_value(&arg, args.next())),
+      "--trace-pixel" => {
+        let value: String = parse_value(&arg, args.next());
+        let pixel = value.split_once(',').and_then(|(i, j)| Some((i.parse().ok()?, j.parse().ok()?)));
+        if pixel.is_none() {
+          eprintln!("Invalid pixel \"{}\" for --trace-pixel, expected i,j.", value);
+          std::process::exit(1);
+        }
+        options.trace_pixel = pixel;
+      },
+      _ => {
+        eprintln!("Unknown argument \"{}\".", arg);
+        std::process::exit(1);
+      },
+    }
+  }
+
+  // 降噪器依赖只有路径追踪器才会记录的反照率、法线和深度 AOV，自适应采样也只在路径追踪器中实现。
+  if options.denoise && options.integrator != "path" {
+    eprintln!("--denoise requires the path integrator.");
+    std::process::exit(1);
+  }
+  if options.adaptive && options.integrator != "path" {
+    eprintln!("--adaptive requires the path integrator.");
+    std::process::exit(1);
+  }
+  if options.russian_roulette.is_some() && options.integrator != "path" {
+    eprintln!("--russian-roulette requires the path integrator.");
+    std::process::exit(1);
+  }
+  if (options.clamp_indirect.is_some() || options.regularize.is_some()) && !matches!(options.integrator.as_str(), "path" | "pssmlt") {
+    eprintln!("--clamp-indirect and --regularize require the path or pssmlt integrator.");
+    std::process::exit(1);
+  }
+  // 跟踪单个像素就是渲染只含该像素的裁剪窗口，同时记录每次反弹。
+  if let Some((i, j)) = options.trace_pixel {
+    if options.integrator != "path" || options.crop.is_some() || options.workers > 1 || options.preview_port.is_some() {
+      eprintln!("--trace-pixel requires the path integrator without --crop, --workers or --preview-port.");
+      std::process::exit(1);
+    }
+    options.crop = Some(CropWindow::pixel(i, j));
+  }
+  // BDPT 连接相机顶点时用到的重要性函数假设光线从针孔或薄透镜出发。
+  if options.projection != "perspective" && options.integrator == "bdpt" {
+    eprintln!("--projection {} cannot be combined with the bdpt integrator.", options.projection);
+    std::process::exit(1);
+  }
+  // 全方向立体渲染的每只眼睛都是等距柱状全景图，平行和内倾立体渲染的每只眼睛都是透视投影。
+  match options.stereo {
+    Some(StereoMode::Omnidirectional) => {
+      if options.projection != "perspective" && options.projection != "equirectangular" {
+        eprintln!("--stereo ods renders equirectangular eyes and cannot be combined with --projection {}.", options.projection);
+        std::process::exit(1);
+      }
+      options.projection = String::from("equirectangular");
+    },
+    Some(mode) if options.projection != "perspective" => {
+      eprintln!("--stereo {} requires the perspective projection.", mode.name());
+      std::process::exit(1);
+    },
+    _ => {},
+  }
+  if options.stereo.is_some() && options.integrator == "bdpt" {
+    eprintln!("--stereo cannot be combined with the bdpt integrator.");
+    std::process::exit(1);
+  }
+  // 真实镜头取代透视投影的理想透镜，BDPT 的重要性函数同样不适用于它。
+  if options.lens.is_some() && (options.projection != "perspective" || options.integrator == "bdpt") {
+    eprintln!("--lens requires the perspective projection and cannot be combined with the bdpt integrator.");
+    std::process::exit(1);
+  }
+  if options.film_diagonal <= 0.0 || options.lens_scale <= 0.0 || options.focus_distance.is_some_and(|d| d <= 0.0) {
+    eprintln!("--film-diagonal, --lens-scale and --focus-distance must be positive.");
+    std::process::exit(1);
+  }
+  if options.defocus_angle < 0.0 || options.cat_eye < 0.0 || options.aperture_blades < 3 || !(0.0..1.0).contains(&options.aperture_inner) {
+    eprintln!("--defocus-angle and --cat-eye must not be negative, --aperture-blades must be at least 3 and --aperture-inner in [0, 1).");
+    std::process::exit(1);
+  }
+  let exposure = &options.exposure;
+  if exposure.f_number <= 0.0 || exposure.focal_length <= 0.0 || exposure.sensor_width <= 0.0 || exposure.iso <= 0.0 {
+    eprintln!("--f-number, --focal-length, --sensor-width and --iso must be positive.");
+    std::process::exit(1);
+  }
+  // 运动物体的包围盒只覆盖时间 0 到 1 之间的位置，卷帘快门的底行也必须在时间 1 之前结束曝光。
+  let shutter = &options.shutter;
+  if !(0.0 <= shutter.open && shutter.open < shutter.close && shutter.readout() >= 0.0 && shutter.end() <= 1.0) {
+    eprintln!("--shutter-open, --shutter-close and --rolling-shutter must satisfy 0 <= open < close and close + readout <= 1.");
+    std::process::exit(1);
+  }
+  // 序列中的每一帧单独写入文件，逐帧的检查点、AOV 和多进程渲染都不支持。
+  if options.animation.is_some() && (options.workers > 1 || options.worker.is_some() || options.checkpoint.is_some() || options.resume.is_some()) {
+    eprintln!("--animation cannot be combined with --workers, --checkpoint or --resume.");
+    std::process::exit(1);
+  }
+  if options.animation.is_some() && (options.aov_output.is_some() || options.sample_heatmap.is_some()) {
+    eprintln!("--animation cannot be combined with --aov-output or --sample-heatmap.");
+    std::process::exit(1);
+  }
+  // --physical 由焦距推出视场角，会覆盖关键帧中的 vfov。
+  if options.animation.is_some() && options.physical {
+    eprintln!("--animation keyframes the field of view and cannot be combined with --physical.");
+    std::process::exit(1);
+  }
+  if options.frame_start.zip(options.frame_end).is_some_and(|(start, end)| start > end) {
+    eprintln!("--frame-start must not be after --frame-end.");
+    std::process::exit(1);
+  }
+  if !options.frame_output.contains('#') {
+    eprintln!("--frame-output must contain a # standing for the frame number.");
+    std::process::exit(1);
+  }
+  let tilt_shift = &options.tilt_shift;
+  if tilt_shift != &TiltShift::default() && (options.projection != "perspective" || options.lens.is_some()) {
+    eprintln!("--shift-x, --shift-y, --tilt-x and --tilt-y require the perspective projection without --lens.");
+    std::process::exit(1);
+  }
+  if tilt_shift.tilt.0.abs() >= 90.0 || tilt_shift.tilt.1.abs() >= 90.0 {
+    eprintln!("--tilt-x and --tilt-y must be in (-90, 90).");
+    std::process::exit(1);
+  }
+  // BDPT 的重要性函数假设对焦平面与 w 垂直，平移视口不影响它。
+  if tilt_shift.tilt != (0.0, 0.0) && options.integrator == "bdpt" {
+    eprintln!("--tilt-x and --tilt-y cannot be combined with the bdpt integrator.");
+    std::process::exit(1);
+  }
+  if (options.aperture == "image") != options.aperture_image.is_some() {
+    eprintln!("--aperture image and --aperture-image must be given together.");
+    std::process::exit(1);
+  }
+  // BDPT 的光源子路径沿用相机样本所在行的时间，但其 t == 1 贡献会落到曝光时间不同的其他行上。
+  if options.shutter.mode != ShutterMode::Global && options.integrator == "bdpt" {
+    eprintln!("--rolling-shutter cannot be combined with the bdpt integrator.");
+    std::process::exit(1);
+  }
+  // BDPT 对透镜采样和求重要性时假设光圈是圆盘。
+  if (options.aperture != "disk" || options.cat_eye > 0.0) && options.integrator == "bdpt" {
+    eprintln!("--aperture other than disk and --cat-eye cannot be combined with the bdpt integrator.");
+    std::process::exit(1);
+  }
+  if options.interocular.is_some_and(|d| d < 0.0) || options.convergence.is_some_and(|d| d <= 0.0) {
+    eprintln!("--interocular must not be negative and --convergence must be positive.");
+    std::process::exit(1);
+  }
+  if options.view_height.is_some_and(|h| h <= 0.0) {
+    eprintln!("--view-height must be positive.");
+    std::process::exit(1);
+  }
+  // 球极投影把视线的反方向映射到无穷远处，因此视场角必须小于 360 度。
+  let max_fov = if options.projection == "fisheye-stereographic" { 359.0 } else { 360.0 };
+  if options.projection.starts_with("fisheye") && (options.fisheye_fov <= 0.0 || options.fisheye_fov > max_fov) {
+    eprintln!("--fisheye-fov must be in (0, {}] for --projection {}.", max_fov, options.projection);
+    std::process::exit(1);
+  }
+  if options.adaptive && (options.min_spp < 2 || options.max_spp < options.min_spp) {
+    eprintln!("--adaptive requires 2 <= --min-spp <= --max-spp.");
+    std::process::exit(1);
+  }
+
+  // 分布式渲染由多个渐进式渲染的工作进程完成，工作进程不读写检查点。
+  // 统计计数器属于各个工作进程，因此 --stats 由工作进程各自输出，而无法导出为一个 JSON 文件。
+  if options.workers > 1 || options.worker.is_some() {
+    if options.resume.is_some() {
+      eprintln!("--workers cannot be combined with --resume.");
+      std::process::exit(1);
+    }
+    if options.stats_json.is_some() {
+      eprintln!("--workers cannot be combined with --stats-json.");
+      std::process::exit(1);
+    }
+    options.progressive = true;
+  }
+
+  // 预览服务器展示的是本进程逐遍累积的结果，因此意味着渐进式渲染。
+  if options.preview_port.is_some() {
+    if options.workers > 1 || options.worker.is_some() {
+      eprintln!("--preview-port cannot be combined with --workers.");
+      std::process::exit(1);
+    }
+    options.progressive = true;
+  }
+
+  // 从检查点恢复意味着渐进式渲染，且默认继续写回同一个检查点。
+  if options.resume.is_some() {
+    options.progressive = true;
+    if options.checkpoint.is_none() {
+      options.checkpoint = options.resume.clone();
+    }
+  }
+  if options.progressive && (options.integrator != "path" || options.adaptive) {
+    eprintln!("--progressive requires the path integrator without --adaptive.");
+    std::process::exit(1);
+  }
+
+  // 渐进式渲染、自适应采样和按像素估计或 splat 的积分器都直接按像素累加样本，只支持默认的盒式滤波器。
+  // BDPT 的 t == 1 贡献同样直接 splat 到像素上，不带滤波器权重，按权重和归一化会使其有偏。
+  if options.filter_radius.is_some_and(|r| r <= 0.0) {
+    eprintln!("--filter-radius must be positive.");
+    std::process::exit(1);
+  }
+  if !filter(&options).is_pixel_box()
+    && (options.progressive || options.adaptive || matches!(options.integrator.as_str(), "bdpt" | "sppm" | "pssmlt"))
+  {
+    eprintln!("--filter other than a half-pixel box cannot be combined with --progressive, --adaptive, bdpt, sppm or pssmlt.");
+    std::process::exit(1);
+  }
+
+  options
+}
+
+fn filter(options: &Options) -> Filter {
+  Filter::new(&options.filter, options.filter_radius).expect("filter names are checked by parse_args")
+}
+
+fn projection(options: &Options, cam: &Camera) -> Projection {
+  let fisheye = |mapping| Projection::Fisheye { fov: options.fisheye_fov, mapping };
+  match options.projection.as_str() {
+    "orthographic" => {
+      // 默认视口高度取透视投影在 lookat 处看到的高度，两种投影下对焦平面上的画面大小一致。
+      let distance = (cam.lookfrom - cam.lookat).length();
+      let view_height = options.view_height.unwrap_or(2.0 * (rtweekend::degrees_to_radians(cam.vfov) / 2.0).tan() * distance);
+      Projection::Orthographic { view_height }
+    },
+    "equirectangular" => Projection::Equirectangular,
+    "cube-map" => Projection::CubeMap,
+    "fisheye-equidistant" => fisheye(FisheyeMapping::Equidistant),
+    "fisheye-equisolid" => fisheye(FisheyeMapping::Equisolid),
+    "fisheye-stereographic" => fisheye(FisheyeMapping::Stereographic),
+    _ => Projection::Perspective,
+  }
+}
+
+fn aperture(options: &Options) -> Aperture {
+  let shape = match options.aperture.as_str() {
+    "polygon" => ApertureShape::Polygon { blades: options.aperture_blades, rotation: options.aperture_rotation },
+    "annulus" => ApertureShape::Annulus { inner_radius: options.aperture_inner },
+    "image" => {
+      let path = options.aperture_image.as_deref().unwrap_or_default();
+      let mut image = RtwImage::default();
+      let aperture_image = if image.load(path) { ApertureImage::new(&image) } else { None };
+      let Some(aperture_image) = aperture_image else {
+        eprintln!("Failed to load \"{}\" as an aperture image, or it is black.", path);
+        std::process::exit(1);
+      };
+      ApertureShape::Image(Rc::new(aperture_image))
+    },
+    _ => ApertureShape::Disk,
+  };
+  Aperture { shape, vignetting: options.cat_eye }
+}
+
+fn lens_system(options: &Options, cam: &Camera) -> Option<LensSystem> {
+  // 读取镜头处方，并检查镜头能否对焦到相机的对焦距离。
+  let path = options.lens.as_ref()?;
+  let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+    eprintln!("Failed to read \"{}\": {}.", path, e);
+    std::process::exit(1);
+  });
+  let mut lens: LensSystem = text.parse().unwrap_or_else(|e| {
+    eprintln!("Invalid lens prescription \"{}\": {}.", path, e);
+    std::process::exit(1);
+  });
+  lens.film_diagonal = options.film_diagonal;
+  lens.mm_per_unit = options.lens_scale;
+
+  if lens.focused(cam.focus_dist * lens.mm_per_unit).is_none() {
+    eprintln!("The lens in \"{}\" cannot focus at a distance of {}.", path, cam.focus_dist);
+    std::process::exit(1);
+  }
+  Some(lens)
+}
+
+fn stereo_distances(options: &Options, cam: &Camera) -> (f64, f64) {
+  // Returns the interocular and convergence distances, by default following the camera's distance to lookat.
+  let convergence = options.convergence.unwrap_or((cam.lookfrom - cam.lookat).length());
+  (options.interocular.unwrap_or(convergence / 30.0), convergence)
+}
+
+fn apply_frame(animation: &CameraAnimation, frame: i64, options: &Options, cam: &mut Camera) {
+  // 把相机设为第 frame 帧的关键帧插值，--focus-distance 优先于关键帧中的对焦距离，
+  // 并重新推出依赖相机位置和视场角的投影参数与立体参数。
+  animation.apply(frame as f64, cam);
+  if let Some(distance) = options.focus_distance {
+    cam.focus_dist = distance;
+  }
+  cam.projection = projection(options, cam);
+  let (interocular, convergence) = stereo_distances(options, cam);
+  if let Some(stereo) = cam.stereo.as_mut() {
+    stereo.interocular = interocular;
+    stereo.convergence = convergence;
+  }
+}
+
+fn camera_animation(options: &Options) -> Option<CameraAnimation> {
+  let path = options.animation.as_ref()?;
+  let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+    eprintln!("Failed to read \"{}\": {}.", path, e);
+    std::process::exit(1);
+  });
+  let animation = text.parse().unwrap_or_else(|e| {
+    eprintln!("Invalid camera keyframes \"{}\": {}.", path, e);
+    std::process::exit(1);
+  });
+  Some(animation)
+}
+
+fn frame_path(pattern: &str, frame: i64) -> String {
+  // 把 pattern 中第一段连续的 # 替换为补零到同样宽度的帧号。
+  let start = pattern.find('#').unwrap_or(pattern.len());
+  let width = pattern[start..].chars().take_while(|&c| c == '#').count();
+  format!("{}{:0width$}{}", &pattern[..start], frame, &pattern[start + width..])
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> T {
+  match value.as_deref().map(str::parse) {
+    Some(Ok(v)) => v,
+    _ => {
+      eprintln!("Missing or invalid value for {}.", name);
+      std::process::exit(1);
+    },
+  }
+}
 
-fn cornell_box() {
+fn cornell_box(options: &Options) {
   let mut world = HittableList::default();
 
   let red: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
@@ -115,16 +701,22 @@ fn cornell_box() {
     )
   ));
 
+  let world = HittableList::new(Rc::new(BvhNode::new(&mut world)));
+
   // Light Sources.
-  let mut lights = HittableList::default();
-  lights.add(Rc::new(
+  let ceiling_light: Rc<dyn Hittable> = Rc::new(
     Quad::new(
       Point3::new(343.0, 554.0, 332.0),
       vec3::Vec3::new(-130.0, 0.0, 0.0),
       vec3::Vec3::new(0.0, 0.0, -105.0),
       Rc::clone(&light),
     )
-  ));
+  );
+  // BDPT 和 SPPM 从光源表面发出光源子路径和光子，只能使用真正发光的物体；玻璃球只用于路径追踪的重要性采样。
+  let mut emitters = HittableList::default();
+  emitters.add(Rc::clone(&ceiling_light));
+  let mut lights = HittableList::default();
+  lights.add(ceiling_light);
   lights.add(Rc::new(
     Sphere::new(
       Point3::new(190.0, 90.0, 190.0),
@@ -146,16 +738,285 @@ fn cornell_box() {
   cam.lookat = Point3::new(278.0, 278.0, 0.0);
   cam.vup = vec3::Vec3::new(0.0, 1.0, 0.0);
 
-  cam.defocus_angle = 0.0;
+  cam.defocus_angle = options.defocus_angle;
+  cam.exposure = options.physical.then_some(Exposure { mm_per_unit: options.lens_scale, ..options.exposure });
+  cam.shutter = options.shutter;
+  cam.aperture = aperture(options);
+  cam.tilt_shift = options.tilt_shift;
+
+  cam.focus_dist = options.focus_distance.unwrap_or((cam.lookfrom - cam.lookat).length());
+  cam.projection = projection(options, &cam);
+  cam.lens = lens_system(options, &cam);
+  // 等距柱状投影和立方体贴图的画面比例由投影本身决定。立方体贴图的宽度取为 3 的倍数，
+  // 每个面恰好是 width / 3 见方的整数个像素，面与面的边界落在像素边界上。
+  match cam.projection {
+    Projection::Equirectangular => cam.aspect_ratio = 2.0,
+    Projection::CubeMap => {
+      cam.image_width = (cam.image_width / 3).max(1) * 3;
+      cam.aspect_ratio = 1.5;
+    },
+    _ => {},
+  }
+
+  // 立体渲染时两只眼睛各占一幅上述大小的图像，按布局上下或左右拼接。
+  if let Some(mode) = options.stereo {
+    let (interocular, convergence) = stereo_distances(options, &cam);
+    let layout = options.stereo_layout;
+    cam.stereo = Some(Stereo {
+      mode,
+      interocular,
+      convergence,
+      layout,
+    });
+    match layout {
+      StereoLayout::TopBottom => cam.aspect_ratio /= 2.0,
+      StereoLayout::SideBySide => {
+        cam.image_width *= 2;
+        cam.aspect_ratio *= 2.0;
+      },
+    }
+  }
+
+  if options.adaptive {
+    cam.adaptive = Some(AdaptiveSampling {
+      min_samples_per_pixel: options.min_spp,
+      max_samples_per_pixel: options.max_spp,
+      target_error: options.target_error,
+    });
+  }
+  cam.russian_roulette = options.russian_roulette;
+  cam.crop = options.crop;
+  cam.log_bounces = options.trace_pixel.is_some();
+  cam.filter = filter(options);
+  cam.indirect_clamp = options.clamp_indirect;
+  cam.regularize = options.regularize;
 
-  cam.render(&world, &lights);
+  // 工作进程把渲染状态写到标准输出，由协调进程合并后再输出图像。
+  if options.worker.is_some() {
+    let state = render_progressive(&mut cam, &world, &lights, options);
+    state.write_checkpoint(&mut std::io::stdout().lock()).unwrap();
+    return;
+  }
+
+  // 动画只改变相机，场景和 BVH 在各帧之间复用。
+  if let Some(animation) = camera_animation(options) {
+    let first = options.frame_start.unwrap_or(animation.first_frame().floor() as i64);
+    let last = options.frame_end.unwrap_or(animation.last_frame().ceil() as i64);
+    // 先检查每一帧的对焦距离，以免渲染到中途才发现镜头无法对焦。
+    for frame in first..=last {
+      apply_frame(&animation, frame, options, &mut cam);
+      if cam.lens.as_ref().is_some_and(|lens| lens.focused(cam.focus_dist * lens.mm_per_unit).is_none()) {
+        eprintln!("The lens cannot focus at a distance of {} in frame {}.", cam.focus_dist, frame);
+        std::process::exit(1);
+      }
+    }
+    for frame in first..=last {
+      let path = frame_path(&options.frame_output, frame);
+      eprintln!("Rendering frame {} of {}..{} to \"{}\".", frame, first, last, path);
+      apply_frame(&animation, frame, options, &mut cam);
+      let film = render_film(&mut cam, &world, &lights, &emitters, options);
+      write_file(&path, |out| film.write_ppm(out, cam.samples_per_pixel));
+    }
+    return;
+  }
+
+  let film = render_film(&mut cam, &world, &lights, &emitters, options);
+  film.write_ppm(&mut std::io::stdout().lock(), cam.samples_per_pixel).unwrap();
+
+  if let Some(path) = &options.aov_output {
+    write_file(path, |out| film.write_exr(out, cam.samples_per_pixel));
+  }
+  if let Some(path) = &options.sample_heatmap {
+    write_file(path, |out| visualize::write_sample_heatmap(&film, out, cam.samples_per_pixel));
+  }
+}
+
+fn render_film(cam: &mut Camera, world: &dyn Hittable, lights: &dyn Hittable, emitters: &dyn Hittable, options: &Options) -> Framebuffer {
+  // 用选定的积分器渲染一帧，需要时再降噪。
+  let mut film = match options.integrator.as_str() {
+    "bdpt" => cam.render_with(&Bdpt, world, emitters),
+    "sppm" => {
+      let sppm = Sppm {
+        photons_per_iteration: options.photons,
+        initial_radius: options.photon_radius,
+        ..Default::default()
+      };
+      cam.render_with(&sppm, world, emitters)
+    },
+    "pssmlt" => cam.render_with(&Pssmlt::default(), world, lights),
+    "normals" => cam.render_with(&DebugIntegrator::new(DebugView::Normals), world, lights),
+    "uv" => cam.render_with(&DebugIntegrator::new(DebugView::Uv), world, lights),
+    "depth" => cam.render_with(&DebugIntegrator::new(DebugView::Depth), world, lights),
+    "front-face" => cam.render_with(&DebugIntegrator::new(DebugView::FrontFace), world, lights),
+    "material-id" => cam.render_with(&DebugIntegrator::new(DebugView::MaterialId), world, lights),
+    "bvh-heatmap" => cam.render_with(&DebugIntegrator::new(DebugView::BvhHeatmap), world, lights),
+    "ao" => cam.render_with(&AmbientOcclusion { radius: options.ao_radius }, world, lights),
+    _ if options.workers > 1 => render_distributed(options),
+    _ if options.progressive => render_progressive(cam, world, lights, options).film,
+    _ => cam.render(world, lights),
+  };
+
+  if options.denoise {
+    let denoiser = AtrousDenoiser {
+      iterations: options.denoise_iterations,
+      ..Default::default()
+    };
+    denoiser.denoise(&mut film, cam.samples_per_pixel);
+  }
+  film
+}
+
+fn render_progressive(cam: &mut Camera, world: &dyn Hittable, lights: &dyn Hittable, options: &Options) -> RenderState {
+  let mut state = match &options.resume {
+    Some(path) => match RenderState::load(path) {
+      Ok(state) => state,
+      Err(e) => {
+        eprintln!("Failed to read checkpoint \"{}\": {}.", path, e);
+        std::process::exit(1);
+      },
+    },
+    None => RenderState::new(options.seed),
+  };
+
+  let save = |state: &RenderState| {
+    if let Some(path) = &options.checkpoint {
+      if let Err(e) = state.save(path) {
+        eprintln!("Failed to write checkpoint \"{}\": {}.", path, e);
+      }
+    }
+  };
+
+  let preview = options.preview_port.map(|port| match PreviewServer::start(port) {
+    Ok(server) => {
+      eprintln!("Previewing at http://127.0.0.1:{}/", server.port());
+      server
+    },
+    Err(e) => {
+      eprintln!("Failed to start the preview server on port {}: {}.", port, e);
+      std::process::exit(1);
+    },
+  });
+
+  let share = options.worker.unwrap_or_default();
+  let total_passes = cam.passes();
+  let spp = cam.samples_per_pixel;
+  let mut last_save = std::time::Instant::now();
+  let mut start = (std::time::Instant::now(), state.passes, stats::counters().rays);
+  let mut cancelled = false;
+  let result = cam.render_progressive(world, lights, &mut state, &share, |state| {
+    if last_save.elapsed().as_secs_f64() >= options.checkpoint_interval {
+      save(state);
+      last_save = std::time::Instant::now();
+    }
+
+    let Some(preview) = &preview else {
+      return true;
+    };
+
+    // ETA 和光线吞吐量只按本次运行（或最近一次重新开始之后）完成的遍数估计。
+    let (start_time, start_passes, start_rays) = start;
+    let elapsed = start_time.elapsed().as_secs_f64();
+    let passes_done = state.passes - start_passes;
+    preview.update(&state.film, spp, Progress {
+      passes: state.passes,
+      total_passes,
+      elapsed_seconds: elapsed,
+      eta_seconds: elapsed / passes_done.max(1) as f64 * (total_passes - state.passes) as f64,
+      rays_per_second: (stats::counters().rays - start_rays) as f64 / elapsed.max(1e-9),
+    });
+
+    match preview.take_request() {
+      preview::Request::Cancel => {
+        cancelled = true;
+        false
+      },
+      preview::Request::Restart => {
+        let (width, height) = (state.film.width(), state.film.height());
+        state.restart(width, height);
+        start = (std::time::Instant::now(), 0, stats::counters().rays);
+        true
+      },
+      preview::Request::None => true,
+    }
+  });
+  if let Err(e) = result {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  }
+  save(&state);
+  if let Some(preview) = &preview {
+    preview.finish(cancelled);
+  }
+
+  state
+}
+
+fn render_distributed(options: &Options) -> Framebuffer {
+  // 工作进程沿用协调进程的命令行，只去掉由协调进程自己处理的参数。
+  let mut args = std::env::args().skip(1);
+  let mut worker_args = Vec::new();
+  while let Some(arg) = args.next() {
+    if COORDINATOR_ARGS.contains(&arg.as_str()) {
+      args.next();
+    } else {
+      worker_args.push(arg);
+    }
+  }
+  match distributed::render_with_workers(options.workers, &worker_args) {
+    Ok(state) => {
+      if let Some(path) = &options.checkpoint {
+        if let Err(e) = state.save(path) {
+          eprintln!("Failed to write checkpoint \"{}\": {}.", path, e);
+        }
+      }
+      state.film
+    },
+    Err(e) => {
+      eprintln!("Distributed rendering failed: {}.", e);
+      std::process::exit(1);
+    },
+  }
+}
+
+fn write_file<F: FnOnce(&mut dyn std::io::Write) -> std::io::Result<()>>(path: &str, write: F) {
+  let result = std::fs::File::create(path).and_then(|file| {
+    let mut out = std::io::BufWriter::new(file);
+    write(&mut out)
+  });
+  if let Err(e) = result {
+    eprintln!("Failed to write \"{}\": {}.", path, e);
+    std::process::exit(1);
+  }
 }
 
 fn main() {
+  let options = parse_args();
+  progress::set_reporter(options.progress.reporter());
   let now = std::time::Instant::now();
 
-  cornell_box();
+  cornell_box(&options);
 
   let elapsed = now.elapsed();
   eprintln!("Elapsed: {}.{:03}s", elapsed.as_secs(), elapsed.subsec_millis());
-}
\ No newline at end of file
+
+  let counters = stats::counters();
+  if options.stats {
+    counters.write_summary(&mut std::io::stderr().lock(), elapsed.as_secs_f64()).unwrap();
+  }
+  if let Some(path) = &options.stats_json {
+    write_file(path, |out| counters.write_json(out, elapsed.as_secs_f64()));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn frame_path_pads_the_first_run_of_hashes() {
+    assert_eq!(frame_path("frame_####.ppm", 7), "frame_0007.ppm");
+    assert_eq!(frame_path("frame_#.ppm", 123), "frame_123.ppm");
+    assert_eq!(frame_path("out/#/##.ppm", 5), "out/5/##.ppm");
+    assert_eq!(frame_path("shot##", -3), "shot-3");
+  }
+}
@@ -1,3 +1,5 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 use super::vec3;
 use super::ray::Ray;
@@ -33,6 +35,20 @@ impl Default for ScatterRecord {
   }
 }
 
+thread_local! {
+  static MATERIAL_IDS: RefCell<HashMap<usize, usize>> = RefCell::new(HashMap::new());
+}
+
+pub fn material_id(mat: &Rc<dyn Material>) -> usize {
+  // Returns the id of a material, numbered from one in the order primitives using it were constructed.
+  let key = Rc::as_ptr(mat) as *const () as usize;
+  MATERIAL_IDS.with(|ids| {
+    let mut ids = ids.borrow_mut();
+    let next = ids.len() + 1;
+    *ids.entry(key).or_insert(next)
+  })
+}
+
 pub trait Material {
   fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool;
   fn emitted(&self, _r_in: &Ray, _rec: &HitRecord, _u: f64, _v: f64, _p: vec3::Point3) -> Color {
@@ -0,0 +1,94 @@
+use super::color::Color;
+use super::vec3::{Vec3, Point3};
+
+#[derive(Clone, Copy)]
+pub struct Photon {
+  pub p: Point3,     // Position the photon landed on
+  pub wi: Vec3,      // Unit direction back towards where the photon came from
+  pub power: Color,  // Flux carried by the photon
+}
+
+pub struct PhotonMap {
+  photons: Vec<Photon>,
+  axes: Vec<usize>,
+}
+
+impl PhotonMap {
+  pub fn new(photons: Vec<Photon>) -> Self {
+    // 建立隐式平衡 kd 树：每个子区间的中位数光子作为节点，并记录其划分轴。
+    let len = photons.len();
+    let mut map = Self {
+      photons,
+      axes: vec![0; len],
+    };
+    map.build(0, len);
+    map
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.photons.is_empty()
+  }
+
+  pub fn for_each_in_radius<F: FnMut(&Photon)>(&self, p: Point3, radius: f64, mut f: F) {
+    // 对与 p 的距离不超过 radius 的每个光子调用 f。
+    self.query(0, self.photons.len(), p, radius * radius, &mut f);
+  }
+
+  fn build(&mut self, start: usize, end: usize) {
+    if end <= start {
+      return;
+    }
+
+    // 沿光子分布最长的轴划分。
+    let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+    for photon in self.photons[start..end].iter() {
+      for a in 0..3 {
+        min[a] = min[a].min(photon.p[a]);
+        max[a] = max[a].max(photon.p[a]);
+      }
+    }
+    let extent = max - min;
+    let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+      0
+    } else if extent.y() > extent.z() {
+      1
+    } else {
+      2
+    };
+
+    let mid = start + (end - start) / 2;
+    self.photons[start..end].select_nth_unstable_by(mid - start, |a, b| {
+      a.p[axis].partial_cmp(&b.p[axis]).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    self.axes[mid] = axis;
+
+    self.build(start, mid);
+    self.build(mid + 1, end);
+  }
+
+  fn query<F: FnMut(&Photon)>(&self, start: usize, end: usize, p: Point3, radius_squared: f64, f: &mut F) {
+    if end <= start {
+      return;
+    }
+
+    let mid = start + (end - start) / 2;
+    let photon = &self.photons[mid];
+    if (photon.p - p).length_squared() <= radius_squared {
+      f(photon);
+    }
+
+    // 先搜索查询点所在的一侧，只有当分割平面落在半径以内时才搜索另一侧。
+    let axis = self.axes[mid];
+    let d = p[axis] - photon.p[axis];
+    let (near, far) = if d < 0.0 {
+      ((start, mid), (mid + 1, end))
+    } else {
+      ((mid + 1, end), (start, mid))
+    };
+    self.query(near.0, near.1, p, radius_squared, f);
+    if d * d <= radius_squared {
+      self.query(far.0, far.1, p, radius_squared, f);
+    }
+  }
+}
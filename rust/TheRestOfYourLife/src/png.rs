@@ -0,0 +1,69 @@
+use std::io::Write;
+
+// 最小化的 PNG 写入器：8 位 RGB，不做滤波，zlib 数据流只使用不压缩的存储块。
+
+fn crc32(bytes: &[u8]) -> u32 {
+  let mut crc = 0xffff_ffff_u32;
+  for &b in bytes {
+    crc ^= b as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+    }
+  }
+  !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+  let (mut a, mut b) = (1_u32, 0_u32);
+  for &byte in bytes {
+    a = (a + byte as u32) % 65521;
+    b = (b + a) % 65521;
+  }
+  (b << 16) | a
+}
+
+fn write_chunk(out: &mut dyn Write, chunk_type: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+  out.write_all(&(data.len() as u32).to_be_bytes())?;
+  let mut crc_input = Vec::with_capacity(4 + data.len());
+  crc_input.extend_from_slice(chunk_type);
+  crc_input.extend_from_slice(data);
+  out.write_all(&crc_input)?;
+  out.write_all(&crc32(&crc_input).to_be_bytes())
+}
+
+pub fn write_png(out: &mut dyn Write, width: usize, height: usize, rgb: &[u8]) -> std::io::Result<()> {
+  // rgb 按行优先存放每个像素的三个字节。
+  out.write_all(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a])?;
+
+  let mut ihdr = Vec::with_capacity(13);
+  ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+  ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+  ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+  write_chunk(out, b"IHDR", &ihdr)?;
+
+  // 每条扫描线前加一个值为 0 的滤波类型字节。
+  let mut raw = Vec::with_capacity(height * (1 + 3 * width));
+  for row in rgb.chunks_exact(3 * width) {
+    raw.push(0);
+    raw.extend_from_slice(row);
+  }
+
+  // zlib 头之后是一串不超过 65535 字节的存储块，最后是原始数据的 Adler-32 校验和。
+  let mut zlib = Vec::with_capacity(raw.len() + raw.len() / 65535 * 5 + 11);
+  zlib.extend_from_slice(&[0x78, 0x01]);
+  let mut blocks = raw.chunks(65535).peekable();
+  if blocks.peek().is_none() {
+    zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+  }
+  while let Some(block) = blocks.next() {
+    let last = blocks.peek().is_none();
+    zlib.push(if last { 1 } else { 0 });
+    zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+    zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+    zlib.extend_from_slice(block);
+  }
+  zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+  write_chunk(out, b"IDAT", &zlib)?;
+
+  write_chunk(out, b"IEND", &[])
+}
@@ -0,0 +1,158 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use super::framebuffer::Framebuffer;
+use super::png;
+
+// 本地 HTTP 预览服务器：只监听回环地址，在后台线程中提供以下接口。
+//   GET  /               定时刷新预览图的页面
+//   GET  /image.png      当前累积结果的 PNG
+//   GET  /progress       JSON 格式的进度
+//   POST /cancel         在当前遍结束后停止渲染
+//   POST /restart        在当前遍结束后丢弃已有结果，从第一遍重新开始
+// 渲染线程每遍结束后调用 update 发布新的图像和进度，并通过 take_request 取得客户端的请求。
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Request {
+  None,
+  Cancel,
+  Restart,
+}
+
+#[derive(Default)]
+pub struct Progress {
+  pub passes: usize,         // Count of completed passes
+  pub total_passes: usize,   // Count of passes the render takes
+  pub elapsed_seconds: f64,  // Time spent rendering since the start or the last restart
+  pub eta_seconds: f64,      // Estimated time until the last pass completes
+  pub rays_per_second: f64,  // Average ray throughput since the start or the last restart
+}
+
+struct Shared {
+  png: Vec<u8>,
+  progress: Progress,
+  status: &'static str,
+  request: Request,
+}
+
+pub struct PreviewServer {
+  shared: Arc<Mutex<Shared>>,
+  port: u16,
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Render preview</title></head>
+<body style="background:#222;color:#ddd;font-family:monospace">
+<img id="image" src="/image.png" style="image-rendering:pixelated;min-width:400px">
+<pre id="progress"></pre>
+<button onclick="fetch('/cancel',{method:'POST'})">Cancel</button>
+<button onclick="fetch('/restart',{method:'POST'})">Restart</button>
+<script>
+setInterval(async () => {
+  document.getElementById('image').src = '/image.png?' + Date.now();
+  const progress = await fetch('/progress');
+  document.getElementById('progress').textContent = await progress.text();
+}, 1000);
+</script>
+</body>
+</html>
+"#;
+
+impl PreviewServer {
+  pub fn start(port: u16) -> std::io::Result<Self> {
+    // 绑定 127.0.0.1 的 port 端口并在后台线程中服务请求，port 为 0 时由系统选择端口。
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let port = listener.local_addr()?.port();
+    let shared = Arc::new(Mutex::new(Shared {
+      png: Vec::new(),
+      progress: Progress::default(),
+      status: "rendering",
+      request: Request::None,
+    }));
+
+    let server_shared = Arc::clone(&shared);
+    std::thread::spawn(move || {
+      for stream in listener.incoming().flatten() {
+        // 单个连接出错不影响服务器继续运行。
+        let _ = Self::handle(stream, &server_shared);
+      }
+    });
+
+    Ok(Self { shared, port })
+  }
+
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  pub fn update(&self, film: &Framebuffer, samples_per_pixel: usize, progress: Progress) {
+    let mut png = Vec::new();
+    let rgb = film.to_rgb8(samples_per_pixel);
+    png::write_png(&mut png, film.width(), film.height(), &rgb).unwrap();
+
+    let mut shared = self.shared.lock().unwrap();
+    shared.png = png;
+    shared.progress = progress;
+  }
+
+  pub fn take_request(&self) -> Request {
+    // Returns the latest cancel or restart request from a client and clears it.
+    let mut shared = self.shared.lock().unwrap();
+    std::mem::replace(&mut shared.request, Request::None)
+  }
+
+  pub fn finish(&self, cancelled: bool) {
+    self.shared.lock().unwrap().status = if cancelled { "cancelled" } else { "done" };
+  }
+
+  fn handle(stream: TcpStream, shared: &Mutex<Shared>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // 请求头对这些接口没有意义，读完即可。
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+      header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+      ("GET", "/") => ("200 OK", "text/html; charset=utf-8", PAGE.as_bytes().to_vec()),
+      ("GET", "/image.png") => ("200 OK", "image/png", shared.lock().unwrap().png.clone()),
+      ("GET", "/progress") => ("200 OK", "application/json", Self::progress_json(&shared.lock().unwrap()).into_bytes()),
+      ("POST", "/cancel") => {
+        shared.lock().unwrap().request = Request::Cancel;
+        ("202 Accepted", "application/json", b"{}".to_vec())
+      },
+      ("POST", "/restart") => {
+        shared.lock().unwrap().request = Request::Restart;
+        ("202 Accepted", "application/json", b"{}".to_vec())
+      },
+      _ => ("404 Not Found", "text/plain", b"Not found".to_vec()),
+    };
+
+    let mut stream = stream;
+    write!(
+      stream,
+      "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+      status, content_type, body.len(),
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+  }
+
+  fn progress_json(shared: &Shared) -> String {
+    let p = &shared.progress;
+    format!(
+      "{{\"status\":\"{}\",\"passes\":{},\"total_passes\":{},\"elapsed_seconds\":{:.3},\"eta_seconds\":{:.3},\"rays_per_second\":{:.0}}}",
+      shared.status, p.passes, p.total_passes, p.elapsed_seconds, p.eta_seconds, p.rays_per_second,
+    )
+  }
+}
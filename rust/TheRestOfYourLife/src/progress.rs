@@ -0,0 +1,266 @@
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::time::Instant;
+
+use super::stats::{self, Counters};
+
+// 渲染进度报告。渲染循环通过 start、advance 和 finish 报告任务的进度，当前线程的报告器负责输出，
+// 和 rtweekend 的随机数源一样按线程设置，因此各个积分器不需要层层传递报告器。
+// 所有输出都写到标准错误，标准输出留给图像。
+
+pub struct Report<'a> {
+  pub task: &'a str,            // Units the task is counted in, e.g. "scanlines"
+  pub done: usize,              // Units completed so far
+  pub total: usize,             // Units the task takes
+  pub elapsed_seconds: f64,     // Time since the task started
+  pub eta_seconds: Option<f64>, // Estimated time until the task completes, once a unit is done
+  pub samples_per_second: f64,  // Camera rays generated per second
+  pub rays_per_second: f64,     // Rays intersected with the scene per second
+  pub counters: Counters,       // Counts accumulated since the task started
+}
+
+pub trait ProgressReporter {
+  fn update(&mut self, report: &Report);
+  fn finish(&mut self, report: &Report);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ProgressStyle {
+  Tty,
+  Log,
+  Json,
+}
+
+impl ProgressStyle {
+  pub fn detect() -> Self {
+    // 标准错误是终端时显示进度条，否则（例如重定向到文件）按固定间隔输出日志行。
+    if std::io::stderr().is_terminal() { Self::Tty } else { Self::Log }
+  }
+
+  pub fn reporter(self) -> Box<dyn ProgressReporter> {
+    match self {
+      Self::Tty => Box::new(TtyReporter),
+      Self::Log => Box::new(LogReporter::default()),
+      Self::Json => Box::new(JsonReporter),
+    }
+  }
+}
+
+impl std::str::FromStr for ProgressStyle {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "tty" => Ok(Self::Tty),
+      "log" => Ok(Self::Log),
+      "json" => Ok(Self::Json),
+      _ => Err(String::from("expected tty, log or json")),
+    }
+  }
+}
+
+pub struct TtyReporter;
+
+impl TtyReporter {
+  const BAR_WIDTH: usize = 30;
+
+  fn line(report: &Report) -> String {
+    let fraction = if report.total > 0 { report.done as f64 / report.total as f64 } else { 1.0 };
+    let filled = ((fraction * Self::BAR_WIDTH as f64) as usize).min(Self::BAR_WIDTH);
+    let eta = report.eta_seconds.map_or(String::from("--:--"), format_duration);
+    format!(
+      "[{}{}] {:3.0}% {}/{} {} | ETA {} | {} samples/s | {} rays/s | {} BVH nodes | {} primitive tests",
+      "#".repeat(filled), " ".repeat(Self::BAR_WIDTH - filled), 100.0 * fraction,
+      report.done, report.total, report.task, eta,
+      format_count(report.samples_per_second), format_count(report.rays_per_second),
+      format_count(report.counters.bvh_node_visits as f64), format_count(report.counters.primitive_tests() as f64),
+    )
+  }
+}
+
+impl ProgressReporter for TtyReporter {
+  fn update(&mut self, report: &Report) {
+    // 回到行首并清除该行，进度条始终只占一行。
+    eprint!("\r\x1b[K{}", Self::line(report));
+  }
+
+  fn finish(&mut self, report: &Report) {
+    eprintln!("\r\x1b[K{} | done in {}", Self::line(report), format_duration(report.elapsed_seconds));
+  }
+}
+
+pub struct LogReporter {
+  pub interval_seconds: f64, // Least time between two log lines
+  last: Option<Instant>,
+}
+
+impl Default for LogReporter {
+  fn default() -> Self {
+    Self {
+      interval_seconds: 10.0,
+      last: None,
+    }
+  }
+}
+
+impl LogReporter {
+  fn print(report: &Report, status: &str) {
+    let eta = report.eta_seconds.map_or(String::from("unknown"), format_duration);
+    eprintln!(
+      "[{:>8}] {} {}/{} {} ({:.0}%), ETA {}, {} samples/s, {} rays/s, {} BVH nodes, {} primitive tests",
+      format_duration(report.elapsed_seconds), status, report.done, report.total, report.task,
+      if report.total > 0 { 100.0 * report.done as f64 / report.total as f64 } else { 100.0 }, eta,
+      format_count(report.samples_per_second), format_count(report.rays_per_second),
+      format_count(report.counters.bvh_node_visits as f64), format_count(report.counters.primitive_tests() as f64),
+    );
+  }
+}
+
+impl ProgressReporter for LogReporter {
+  fn update(&mut self, report: &Report) {
+    // 第一次更新立即输出，之后每隔 interval_seconds 秒最多输出一行。
+    if self.last.is_some_and(|last| last.elapsed().as_secs_f64() < self.interval_seconds) {
+      return;
+    }
+    self.last = Some(Instant::now());
+    Self::print(report, "Rendering");
+  }
+
+  fn finish(&mut self, report: &Report) {
+    self.last = None;
+    Self::print(report, "Done");
+  }
+}
+
+pub struct JsonReporter;
+
+impl JsonReporter {
+  fn print(report: &Report, event: &str) {
+    let eta = report.eta_seconds.map_or(String::from("null"), |eta| format!("{:.3}", eta));
+    let c = &report.counters;
+    eprintln!(
+      "{{\"event\":\"{}\",\"task\":\"{}\",\"done\":{},\"total\":{},\"elapsed_seconds\":{:.3},\"eta_seconds\":{},\
+       \"samples_per_second\":{:.0},\"rays_per_second\":{:.0},\"camera_rays\":{},\"rays\":{},\
+       \"bvh_node_visits\":{},\"primitive_tests\":{}}}",
+      event, report.task, report.done, report.total, report.elapsed_seconds, eta,
+      report.samples_per_second, report.rays_per_second, c.camera_rays, c.rays,
+      c.bvh_node_visits, c.primitive_tests(),
+    );
+  }
+}
+
+impl ProgressReporter for JsonReporter {
+  fn update(&mut self, report: &Report) {
+    Self::print(report, "progress");
+  }
+
+  fn finish(&mut self, report: &Report) {
+    Self::print(report, "done");
+  }
+}
+
+struct Task {
+  name: &'static str,
+  total: usize,
+  done: usize,
+  start: Instant,      // Time the rates and the ETA are measured from
+  start_done: usize,   // Units already done at start, e.g. passes restored from a checkpoint
+  counters: Counters,  // Snapshot of the counters at start
+}
+
+impl Task {
+  fn report(&self) -> Report<'_> {
+    let elapsed = self.start.elapsed().as_secs_f64();
+    let counters = &stats::counters() - &self.counters;
+    let per_second = |n: u64| if elapsed > 0.0 { n as f64 / elapsed } else { 0.0 };
+    let done_here = self.done - self.start_done;
+
+    Report {
+      task: self.name,
+      done: self.done,
+      total: self.total,
+      elapsed_seconds: elapsed,
+      eta_seconds: (done_here > 0).then(|| elapsed / done_here as f64 * self.total.saturating_sub(self.done) as f64),
+      samples_per_second: per_second(counters.camera_rays),
+      rays_per_second: per_second(counters.rays),
+      counters,
+    }
+  }
+}
+
+thread_local! {
+  static REPORTER: RefCell<Box<dyn ProgressReporter>> = RefCell::new(ProgressStyle::detect().reporter());
+  static TASK: RefCell<Option<Task>> = const { RefCell::new(None) };
+}
+
+pub fn set_reporter(reporter: Box<dyn ProgressReporter>) {
+  // Replaces the reporter progress on this thread is written to.
+  REPORTER.with(|r| *r.borrow_mut() = reporter);
+}
+
+pub fn start(task: &'static str, total: usize, done: usize) {
+  // 开始一个共 total 个单位的任务，其中 done 个单位在开始前已经完成。
+  TASK.with(|t| {
+    *t.borrow_mut() = Some(Task {
+      name: task,
+      total,
+      done,
+      start: Instant::now(),
+      start_done: done,
+      counters: stats::counters(),
+    })
+  });
+  report(false);
+}
+
+pub fn advance(done: usize) {
+  // 报告当前任务已完成 done 个单位。done 变小说明任务重新开始了，此时重新计时。
+  TASK.with(|t| {
+    if let Some(task) = t.borrow_mut().as_mut() {
+      if done < task.done {
+        task.start = Instant::now();
+        task.start_done = done;
+        task.counters = stats::counters();
+      }
+      task.done = done;
+    }
+  });
+  report(false);
+}
+
+pub fn finish() {
+  report(true);
+  TASK.with(|t| *t.borrow_mut() = None);
+}
+
+fn report(finished: bool) {
+  TASK.with(|t| {
+    if let Some(task) = t.borrow().as_ref() {
+      let report = task.report();
+      REPORTER.with(|r| {
+        let mut reporter = r.borrow_mut();
+        if finished { reporter.finish(&report) } else { reporter.update(&report) }
+      });
+    }
+  });
+}
+
+fn format_duration(seconds: f64) -> String {
+  // 格式化为 m:ss 或 h:mm:ss。
+  let seconds = seconds.max(0.0).round() as u64;
+  let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+  if h > 0 { format!("{}:{:02}:{:02}", h, m, s) } else { format!("{}:{:02}", m, s) }
+}
+
+fn format_count(n: f64) -> String {
+  // 用 k、M、G 后缀缩写大数。
+  if n >= 1e9 {
+    format!("{:.2}G", n / 1e9)
+  } else if n >= 1e6 {
+    format!("{:.2}M", n / 1e6)
+  } else if n >= 1e3 {
+    format!("{:.2}k", n / 1e3)
+  } else {
+    format!("{:.0}", n)
+  }
+}
@@ -0,0 +1,228 @@
+use std::io::{Read, Write};
+
+use super::framebuffer::Framebuffer;
+
+// 渐进式渲染的状态：每一遍为每个像素追加一个样本，每个样本的随机数序列只由种子、遍数和像素决定，
+// 因此从检查点恢复后继续渲染与不中断地渲染得到逐位相同的结果。
+
+const MAGIC: &[u8; 8] = b"RTWCKPT1";
+
+// 分布式渲染时图像被划分为 TILE_SIZE x TILE_SIZE 的图块，按扫描线顺序编号后轮流分给各个工作进程。
+const TILE_SIZE: usize = 16;
+
+#[derive(Clone, Copy)]
+pub struct WorkerShare {
+  pub index: usize, // Index of this worker, in 0..count
+  pub count: usize, // Count of workers the image is split between
+}
+
+impl Default for WorkerShare {
+  fn default() -> Self {
+    Self {
+      index: 0,
+      count: 1,
+    }
+  }
+}
+
+impl WorkerShare {
+  pub fn owns(&self, i: usize, j: usize, width: usize) -> bool {
+    // Returns whether pixel i,j of an image width pixels wide belongs to this worker's tiles.
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let tile = (j / TILE_SIZE) * tiles_x + i / TILE_SIZE;
+    tile % self.count == self.index
+  }
+}
+
+impl std::str::FromStr for WorkerShare {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    // 解析 "index/count" 形式的字符串。
+    let (index, count) = s.split_once('/').ok_or("expected index/count")?;
+    let index: usize = index.parse().map_err(|_| "invalid worker index")?;
+    let count: usize = count.parse().map_err(|_| "invalid worker count")?;
+    if index >= count {
+      return Err(String::from("worker index out of range"));
+    }
+    Ok(Self { index, count })
+  }
+}
+
+pub struct RenderState {
+  pub film: Framebuffer, // Per-pixel sums, AOVs and sample counts of the passes done so far
+  pub seed: u64,         // Seed all per-sample random sequences are derived from
+  pub passes: usize,     // Count of completed passes
+}
+
+impl RenderState {
+  pub fn new(seed: u64) -> Self {
+    // 帧缓冲在渲染开始时按相机的图像尺寸分配。
+    let mut state = Self {
+      film: Framebuffer::new(0, 0),
+      seed,
+      passes: 0,
+    };
+    state.restart(0, 0);
+    state
+  }
+
+  pub fn restart(&mut self, width: usize, height: usize) {
+    // 丢弃已完成的所有遍，换成给定尺寸的空帧缓冲。
+    self.film = Framebuffer::new_with_aovs(width, height);
+    self.film.enable_sample_counts();
+    self.passes = 0;
+  }
+
+  pub fn merge(&mut self, other: &RenderState) -> Result<(), String> {
+    // 合并另一个工作进程渲染的互不相交的图块。各像素只由一个工作进程渲染，
+    // 其它进程的对应像素都为零，因此合并结果与单进程渲染逐位相同。
+    let film = &other.film;
+    if film.width() != self.film.width() || film.height() != self.film.height() {
+      return Err(String::from("Render states differ in image size."));
+    }
+    if other.seed != self.seed || other.passes != self.passes {
+      return Err(String::from("Render states differ in seed or completed passes."));
+    }
+
+    for j in 0..film.height() {
+      for i in 0..film.width() {
+        self.film.add(i, j, film.pixel(i, j));
+        self.film.add_aov(i, j, film.aov(i, j));
+        self.film.add_sample_count(i, j, film.sample_count(i, j, 0));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn write_checkpoint(&self, out: &mut dyn Write) -> std::io::Result<()> {
+    out.write_all(MAGIC)?;
+    for v in [self.film.width() as u64, self.film.height() as u64, self.seed, self.passes as u64] {
+      out.write_all(&v.to_le_bytes())?;
+    }
+    self.film.write_sums(out)
+  }
+
+  pub fn read_checkpoint(input: &mut dyn Read) -> std::io::Result<Self> {
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "not a checkpoint file"));
+    }
+
+    let mut header = [0_u64; 4];
+    for v in header.iter_mut() {
+      let mut bytes = [0; 8];
+      input.read_exact(&mut bytes)?;
+      *v = u64::from_le_bytes(bytes);
+    }
+    let [width, height, seed, passes] = header;
+
+    Ok(Self {
+      film: Framebuffer::read_sums(input, width as usize, height as usize)?,
+      seed,
+      passes: passes as usize,
+    })
+  }
+
+  pub fn save(&self, path: &str) -> std::io::Result<()> {
+    // 先写入临时文件再重命名，这样进程在写入途中被杀死也不会损坏已有的检查点。
+    let temporary = format!("{}.tmp", path);
+    {
+      let mut out = std::io::BufWriter::new(std::fs::File::create(&temporary)?);
+      self.write_checkpoint(&mut out)?;
+      out.flush()?;
+    }
+    std::fs::rename(&temporary, path)
+  }
+
+  pub fn load(path: &str) -> std::io::Result<Self> {
+    let mut input = std::io::BufReader::new(std::fs::File::open(path)?);
+    Self::read_checkpoint(&mut input)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::camera::Camera;
+  use crate::color::Color;
+  use crate::hittable::Hittable;
+  use crate::hittable_list::HittableList;
+  use crate::material::{DiffuseLight, Lambertian};
+  use crate::quad::Quad;
+  use crate::sphere::Sphere;
+  use crate::vec3::{Point3, Vec3};
+
+  fn scene() -> (Camera, HittableList, HittableList) {
+    let light: Rc<dyn Hittable> = Rc::new(Quad::new(
+      Point3::new(-1.0, 2.0, -1.0),
+      Vec3::new(2.0, 0.0, 0.0),
+      Vec3::new(0.0, 0.0, 2.0),
+      Rc::new(DiffuseLight::new_with_color(Color::new(4.0, 4.0, 4.0))),
+    ));
+    let mut world = HittableList::default();
+    world.add(Rc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Rc::new(Lambertian::new(Color::new(0.5, 0.4, 0.3))))));
+    world.add(Rc::clone(&light));
+    let mut lights = HittableList::default();
+    lights.add(light);
+
+    let mut cam = Camera::default();
+    cam.image_width = 6;
+    cam.samples_per_pixel = 4;
+    cam.max_depth = 4;
+    cam.lookfrom = Point3::new(0.0, 0.0, -4.0);
+    (cam, world, lights)
+  }
+
+  fn checkpoint(state: &RenderState) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    state.write_checkpoint(&mut bytes).unwrap();
+    bytes
+  }
+
+  #[test]
+  fn resumed_render_matches_uninterrupted() {
+    let (mut cam, world, lights) = scene();
+    let mut uninterrupted = RenderState::new(7);
+    cam.render_progressive(&world, &lights, &mut uninterrupted, &WorkerShare::default(), |_| true).unwrap();
+
+    // 第一遍之后停下，经检查点写出再读入后继续渲染。
+    let mut interrupted = RenderState::new(7);
+    cam.render_progressive(&world, &lights, &mut interrupted, &WorkerShare::default(), |_| false).unwrap();
+    assert_eq!(interrupted.passes, 1);
+    let mut resumed = RenderState::read_checkpoint(&mut checkpoint(&interrupted).as_slice()).unwrap();
+    cam.render_progressive(&world, &lights, &mut resumed, &WorkerShare::default(), |_| true).unwrap();
+
+    assert_eq!(resumed.passes, cam.passes());
+    assert!(checkpoint(&resumed) == checkpoint(&uninterrupted));
+  }
+
+  #[test]
+  fn merged_worker_shares_match_single_process() {
+    // 图像跨越多个图块，每个工作进程都分到像素，合并时确实组合了多个进程的结果。
+    let (mut cam, world, lights) = scene();
+    cam.image_width = 2 * TILE_SIZE + 8;
+    let size = cam.image_width;
+    for index in 0..3 {
+      let share = WorkerShare { index, count: 3 };
+      assert!((0..size).any(|j| (0..size).any(|i| share.owns(i, j, size))));
+    }
+
+    let mut single = RenderState::new(3);
+    cam.render_progressive(&world, &lights, &mut single, &WorkerShare::default(), |_| true).unwrap();
+
+    let mut merged: Option<RenderState> = None;
+    for index in 0..3 {
+      let mut state = RenderState::new(3);
+      cam.render_progressive(&world, &lights, &mut state, &WorkerShare { index, count: 3 }, |_| true).unwrap();
+      match merged.as_mut() {
+        None => merged = Some(state),
+        Some(m) => m.merge(&state).unwrap(),
+      }
+    }
+    assert!(checkpoint(&merged.unwrap()) == checkpoint(&single));
+  }
+}
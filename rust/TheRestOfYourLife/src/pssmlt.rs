@@ -0,0 +1,228 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use super::rtweekend::{self, RandomSource};
+use super::color::Color;
+use super::camera::Camera;
+use super::hittable::Hittable;
+use super::integrator::Integrator;
+use super::framebuffer::Framebuffer;
+use super::progress;
+
+// 主样本空间 Metropolis 光传输（PSSMLT）：路径追踪器消耗的随机数被看作单位超立方体中的一个点，
+// 通过对这些随机数做大步（重新均匀采样）和小步（正态扰动）变异来构造马尔可夫链。
+// 材质、PDF 和几何体都不需要修改，只需在 rtweekend 中替换它们取随机数的来源。
+pub struct Pssmlt {
+  pub bootstrap_samples: usize,     // Count of paths used to estimate the normalization constant
+  pub chains: usize,                // Count of independent Markov chains
+  pub sigma: f64,                   // Standard deviation of small-step mutations
+  pub large_step_probability: f64,  // Probability of a large-step mutation
+}
+
+impl Default for Pssmlt {
+  fn default() -> Self {
+    Self {
+      bootstrap_samples: 100_000,
+      chains: 1000,
+      sigma: 0.01,
+      large_step_probability: 0.3,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Default)]
+struct PrimarySample {
+  value: f64,
+  last_modification_iteration: u64,
+  value_backup: f64,
+  modify_backup: u64,
+}
+
+struct MltSampler {
+  rng: StdRng,
+  sigma: f64,
+  large_step_probability: f64,
+  x: Vec<PrimarySample>,
+  current_iteration: u64,
+  large_step: bool,
+  last_large_step_iteration: u64,
+  sample_index: usize,
+}
+
+impl MltSampler {
+  fn new(seed: u64, sigma: f64, large_step_probability: f64) -> Self {
+    Self {
+      rng: StdRng::seed_from_u64(seed),
+      sigma,
+      large_step_probability,
+      x: Vec::new(),
+      current_iteration: 0,
+      large_step: true,
+      last_large_step_iteration: 0,
+      sample_index: 0,
+    }
+  }
+
+  fn start_iteration(&mut self) {
+    self.current_iteration += 1;
+    self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+    self.sample_index = 0;
+  }
+
+  fn accept(&mut self) {
+    if self.large_step {
+      self.last_large_step_iteration = self.current_iteration;
+    }
+  }
+
+  fn reject(&mut self) {
+    for xi in self.x.iter_mut() {
+      if xi.last_modification_iteration == self.current_iteration {
+        xi.value = xi.value_backup;
+        xi.last_modification_iteration = xi.modify_backup;
+      }
+    }
+    self.current_iteration -= 1;
+  }
+
+  fn ensure_ready(&mut self, index: usize) {
+    if index >= self.x.len() {
+      self.x.resize(index + 1, PrimarySample::default());
+    }
+    let xi = &mut self.x[index];
+
+    // 如果样本在最近一次大步之后还未被访问过，则先补上那次大步的均匀采样。
+    if xi.last_modification_iteration < self.last_large_step_iteration {
+      xi.value = self.rng.gen::<f64>();
+      xi.last_modification_iteration = self.last_large_step_iteration;
+    }
+
+    xi.value_backup = xi.value;
+    xi.modify_backup = xi.last_modification_iteration;
+
+    if self.large_step {
+      xi.value = self.rng.gen::<f64>();
+    } else {
+      // 一次性补上自上次修改以来错过的所有小步变异。
+      let n_small = (self.current_iteration - xi.last_modification_iteration) as f64;
+      let effective_sigma = self.sigma * n_small.sqrt();
+      xi.value += Self::standard_normal(&mut self.rng) * effective_sigma;
+      xi.value -= xi.value.floor();
+    }
+    xi.last_modification_iteration = self.current_iteration;
+  }
+
+  fn standard_normal(rng: &mut StdRng) -> f64 {
+    // Box-Muller 变换。
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * rtweekend::PI * u2).cos()
+  }
+}
+
+impl RandomSource for MltSampler {
+  fn next_double(&mut self) -> f64 {
+    let index = self.sample_index;
+    self.sample_index += 1;
+    self.ensure_ready(index);
+    self.x[index].value
+  }
+}
+
+impl Pssmlt {
+  fn luminance(c: Color) -> f64 {
+    let y = c.luminance();
+    if y.is_finite() { y.max(0.0) } else { 0.0 }
+  }
+
+  fn l(sampler: &Rc<RefCell<MltSampler>>, cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable) -> (Color, (f64, f64)) {
+    // 用采样器提供的随机数追踪一条相机路径，返回其辐射亮度和光栅坐标。
+    let previous = rtweekend::set_random_source(Some(sampler.clone()));
+
+    let x = rtweekend::random_double() * cam.film_width() as f64;
+    let y = rtweekend::random_double() * cam.film_height() as f64;
+    let sample = cam.get_ray_sample_at(x, y);
+    let l = if sample.weight > 0.0 {
+      sample.weight * cam.ray_color(&sample.ray, cam.max_depth, world, lights)
+    } else {
+      Color::default()
+    };
+
+    rtweekend::set_random_source(previous);
+    (l, (x, y))
+  }
+}
+
+impl Integrator for Pssmlt {
+  fn render(&self, cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable, film: &mut Framebuffer) {
+    // 自举阶段：估计归一化常数 b，并记录每个种子对应路径的权重，以便按权重选择链的起点。
+    progress::start("bootstrap paths", self.bootstrap_samples, 0);
+    let mut bootstrap_weights = Vec::with_capacity(self.bootstrap_samples);
+    for i in 0..self.bootstrap_samples {
+      let sampler = Rc::new(RefCell::new(MltSampler::new(i as u64, self.sigma, self.large_step_probability)));
+      let (l, _) = Self::l(&sampler, cam, world, lights);
+      bootstrap_weights.push(Self::luminance(l));
+      if (i + 1) % 1000 == 0 {
+        progress::advance(i + 1);
+      }
+    }
+    progress::advance(self.bootstrap_samples);
+    progress::finish();
+    let weight_sum: f64 = bootstrap_weights.iter().sum();
+    if weight_sum <= 0.0 {
+      return;
+    }
+    let b = weight_sum / self.bootstrap_samples as f64;
+
+    let mut cdf = Vec::with_capacity(bootstrap_weights.len());
+    let mut running = 0.0;
+    for w in bootstrap_weights.iter() {
+      running += w / weight_sum;
+      cdf.push(running);
+    }
+
+    // film 保存样本总和，写出时除以 samples_per_pixel，因此总变异数按每像素 samples_per_pixel 次分配。
+    let total_mutations = cam.samples_per_pixel * cam.film_width() * cam.film_height();
+    let chain_mutations = total_mutations.div_ceil(self.chains);
+
+    progress::start("chains", self.chains, 0);
+    for chain in 0..self.chains {
+      let u = rtweekend::random_double();
+      let seed = cdf.partition_point(|&c| c < u).min(cdf.len() - 1);
+      let sampler = Rc::new(RefCell::new(MltSampler::new(seed as u64, self.sigma, self.large_step_probability)));
+
+      let (mut l_current, mut p_current) = Self::l(&sampler, cam, world, lights);
+      let mut i_current = Self::luminance(l_current);
+
+      for _ in 0..chain_mutations {
+        sampler.borrow_mut().start_iteration();
+        let (l_proposed, p_proposed) = Self::l(&sampler, cam, world, lights);
+        let i_proposed = Self::luminance(l_proposed);
+
+        let accept = if i_current > 0.0 { (i_proposed / i_current).min(1.0) } else { 1.0 };
+
+        // 同时累加当前状态和提议状态的期望贡献，以降低方差。
+        if accept > 0.0 && i_proposed > 0.0 {
+          film.splat(p_proposed.0, p_proposed.1, l_proposed * (b * accept / i_proposed));
+        }
+        if accept < 1.0 && i_current > 0.0 {
+          film.splat(p_current.0, p_current.1, l_current * (b * (1.0 - accept) / i_current));
+        }
+
+        if rtweekend::random_double() < accept {
+          l_current = l_proposed;
+          p_current = p_proposed;
+          i_current = i_proposed;
+          sampler.borrow_mut().accept();
+        } else {
+          sampler.borrow_mut().reject();
+        }
+      }
+      progress::advance(chain + 1);
+    }
+    progress::finish();
+  }
+}
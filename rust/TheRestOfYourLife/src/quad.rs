@@ -6,15 +6,17 @@ use super::vec3::{
   Vec3,
   Point3,
 };
-use super::material::Material;
+use super::material::{self, Material};
 use super::aabb::Aabb;
 use super::hittable::{
+  self,
   HitRecord,
   Hittable,
 };
 use super::interval::Interval;
 use super::ray::Ray;
 use super::hittable_list::HittableList;
+use super::stats;
 
 pub struct Quad {
   q: Point3,
@@ -24,6 +26,8 @@ pub struct Quad {
   normal: Vec3,
   d: f64,
   mat: Rc<dyn Material>,
+  id: usize,
+  material_id: usize,
   bbox: Aabb,
   area: f64,
 }
@@ -39,6 +43,8 @@ impl Quad {
       w: n / vec3::dot(n, n),
       normal,
       d: vec3::dot(normal, q),
+      id: hittable::next_object_id(),
+      material_id: material::material_id(&mat),
       mat,
       bbox: Aabb::new_with_point(
         &q, &(q + u + v)
@@ -62,6 +68,7 @@ impl Quad {
 
 impl Hittable for Quad {
   fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+    stats::count(|c| c.quad_tests += 1);
     let denom = vec3::dot(self.normal, r.direction());
 
     // 如果射线与平面平行，则没有相交。
@@ -89,6 +96,8 @@ impl Hittable for Quad {
     rec.t = t;
     rec.p = intersection;
     rec.mat = Some(Rc::clone(&self.mat));
+    rec.object_id = self.id;
+    rec.material_id = self.material_id;
     rec.set_face_normal(r, self.normal);
 
     true
@@ -114,6 +123,37 @@ impl Hittable for Quad {
     let p = self.q + (rtweekend::random_double() * self.u) + (rtweekend::random_double() * self.v);
     p - origin
   }
+
+  fn random_on_surface(&self, rec: &mut HitRecord, _time: f64) -> f64 {
+    // 在四边形上均匀采样一个点，法线取四边形的正面朝向。
+    let a = rtweekend::random_double();
+    let b = rtweekend::random_double();
+
+    rec.p = self.q + (a * self.u) + (b * self.v);
+    rec.normal = self.normal;
+    rec.front_face = true;
+    rec.t = 0.0;
+    rec.u = a;
+    rec.v = b;
+    rec.mat = Some(Rc::clone(&self.mat));
+
+    1.0 / self.area
+  }
+
+  fn surface_pdf_value(&self, p: Point3, _time: f64) -> f64 {
+    if (vec3::dot(self.normal, p) - self.d).abs() > 1e-6 * (1.0 + self.d.abs()) {
+      return 0.0;
+    }
+
+    let planar_hitpt_vector = p - self.q;
+    let alpha = vec3::dot(self.w, vec3::cross(planar_hitpt_vector, self.v));
+    let beta = vec3::dot(self.w, vec3::cross(self.u, planar_hitpt_vector));
+    if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+      return 0.0;
+    }
+
+    1.0 / self.area
+  }
 }
 
 pub fn make_box(a: Point3, b: Point3, mat: Rc<dyn Material>) -> Rc<HittableList> {
@@ -1,4 +1,7 @@
-pub const INFINITY: f64 = std::f64::INFINITY;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const INFINITY: f64 = f64::INFINITY;
 pub const PI: f64 = std::f64::consts::PI;
 
 // Utility Functions
@@ -7,9 +10,63 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
   degrees * PI / 180.0
 }
 
+pub trait RandomSource {
+  // Returns the next random real in [0,1).
+  fn next_double(&mut self) -> f64;
+}
+
+pub struct Pcg32 {
+  state: u64,
+  increment: u64,
+}
+
+impl Pcg32 {
+  pub fn new(seed: u64, sequence: u64) -> Self {
+    // 不同的 sequence 给出互相独立的随机数序列，相同的 seed 和 sequence 总是给出相同的序列。
+    let mut rng = Self {
+      state: 0,
+      increment: (sequence << 1) | 1,
+    };
+    rng.next_u32();
+    rng.state = rng.state.wrapping_add(seed);
+    rng.next_u32();
+    rng
+  }
+
+  pub fn next_u32(&mut self) -> u32 {
+    // PCG-XSH-RR：64 位线性同余状态，输出时做异或移位和随机旋转。
+    let old_state = self.state;
+    self.state = old_state.wrapping_mul(6364136223846793005).wrapping_add(self.increment);
+    let xorshifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+    let rot = (old_state >> 59) as u32;
+    xorshifted.rotate_right(rot)
+  }
+}
+
+impl RandomSource for Pcg32 {
+  fn next_double(&mut self) -> f64 {
+    // 用两次输出拼出 53 位尾数。
+    let bits = ((self.next_u32() as u64) << 32 | self.next_u32() as u64) >> 11;
+    bits as f64 * (1.0 / (1_u64 << 53) as f64)
+  }
+}
+
+thread_local! {
+  static RANDOM_SOURCE: RefCell<Option<Rc<RefCell<dyn RandomSource>>>> = const { RefCell::new(None) };
+}
+
+pub fn set_random_source(source: Option<Rc<RefCell<dyn RandomSource>>>) -> Option<Rc<RefCell<dyn RandomSource>>> {
+  // Replaces the source random_double draws from on this thread and returns the previous one.
+  // Without a source, random_double falls back to the thread-local generator of the rand crate.
+  RANDOM_SOURCE.with(|s| s.replace(source))
+}
+
 pub fn random_double() -> f64 {
   // Returns a random real in [0,1).
-  rand::random::<f64>()
+  RANDOM_SOURCE.with(|s| match s.borrow().as_ref() {
+    Some(source) => source.borrow_mut().next_double(),
+    None => rand::random::<f64>(),
+  })
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
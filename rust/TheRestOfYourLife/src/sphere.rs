@@ -7,19 +7,23 @@ use super::vec3::{
 };
 use super::ray::Ray;
 use super::hittable::{
+  self,
   HitRecord,
   Hittable,
 };
 use super::interval::Interval;
-use super::material::Material;
+use super::material::{self, Material};
 use super::aabb::Aabb;
 use super::rtweekend;
 use super::onb::Onb;
+use super::stats;
 
 pub struct Sphere {
   center1: Point3,
   radius: f64,
   mat: Rc<dyn Material>,
+  id: usize,
+  material_id: usize,
   is_moving: bool,
   center_vec: Vec3,
   bbox: Aabb,
@@ -31,7 +35,9 @@ impl Sphere {
     Self {
       center1: center,
       radius,
+      material_id: material::material_id(&material),
       mat: material,
+      id: hittable::next_object_id(),
       is_moving: false,
       center_vec: Vec3::default(),
       bbox: Aabb::new_with_point(&(center - rvec), &(center + rvec)),
@@ -45,7 +51,9 @@ impl Sphere {
     Self {
       center1,
       radius,
+      material_id: material::material_id(&material),
       mat: material,
+      id: hittable::next_object_id(),
       is_moving: true,
       center_vec: center2 - center1,
       bbox: Aabb::new_with_box(&box1, &box2),
@@ -85,6 +93,7 @@ impl Sphere {
 
 impl Hittable for Sphere {
   fn hit(&self, r: &Ray, ray_t: &Interval, hit_record: &mut HitRecord) -> bool {
+    stats::count(|c| c.sphere_tests += 1);
     let center = if self.is_moving { self.sphere_center(r.time()) } else { self.center1 };
     let oc = center - r.origin();
     let a = r.direction().length_squared();
@@ -112,6 +121,8 @@ impl Hittable for Sphere {
     hit_record.set_face_normal(r, outward_normal);
     (hit_record.u, hit_record.v) = Self::get_sphere_uv(outward_normal);
     hit_record.mat = Some(Rc::clone(&self.mat));
+    hit_record.object_id = self.id;
+    hit_record.material_id = self.material_id;
 
     true
   }
@@ -140,4 +151,26 @@ impl Hittable for Sphere {
     let uvw = Onb::new_from_w(direction);
     uvw.local_v(Self::random_to_sphere(self.radius, distance_squared))
   }
+
+  fn random_on_surface(&self, rec: &mut HitRecord, time: f64) -> f64 {
+    // 运动的球体在 time 时刻的球心处采样。
+    let outward_normal = vec3::random_unit_vector();
+
+    rec.p = self.sphere_center(time) + self.radius * outward_normal;
+    rec.normal = outward_normal;
+    rec.front_face = true;
+    rec.t = 0.0;
+    (rec.u, rec.v) = Self::get_sphere_uv(outward_normal);
+    rec.mat = Some(Rc::clone(&self.mat));
+
+    1.0 / (4.0 * rtweekend::PI * self.radius * self.radius)
+  }
+
+  fn surface_pdf_value(&self, p: Point3, time: f64) -> f64 {
+    if ((p - self.sphere_center(time)).length() - self.radius).abs() > 1e-6 * (1.0 + self.radius) {
+      return 0.0;
+    }
+
+    1.0 / (4.0 * rtweekend::PI * self.radius * self.radius)
+  }
 }
\ No newline at end of file
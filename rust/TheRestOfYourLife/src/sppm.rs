@@ -0,0 +1,250 @@
+use std::rc::Rc;
+
+use super::rtweekend;
+use super::color::Color;
+use super::camera::Camera;
+use super::hittable::{HitRecord, Hittable};
+use super::integrator::Integrator;
+use super::framebuffer::Framebuffer;
+use super::interval::Interval;
+use super::material::{Material, ScatterRecord};
+use super::onb::Onb;
+use super::photon_map::{Photon, PhotonMap};
+use super::ray::Ray;
+use super::vec3::{self, Vec3};
+use super::progress;
+use super::stats;
+
+// 随机渐进式光子映射（SPPM）：每次迭代先从相机追踪可见点，再从光源发射光子并存入 kd 树，
+// 最后在可见点处做密度估计，并按渐进规则收缩各像素的收集半径。
+// 迭代次数等于相机的 samples_per_pixel。
+pub struct Sppm {
+  pub photons_per_iteration: usize, // Count of photons shot from the lights in each iteration
+  pub initial_radius: f64,          // Initial photon gather radius of every pixel
+  pub alpha: f64,                   // Fraction of new photons kept after each iteration
+}
+
+impl Default for Sppm {
+  fn default() -> Self {
+    Self {
+      photons_per_iteration: 100_000,
+      initial_radius: 5.0,
+      alpha: 2.0 / 3.0,
+    }
+  }
+}
+
+struct VisiblePoint {
+  rec: HitRecord,
+  mat: Rc<dyn Material>,
+  attenuation: Color,
+  wo: Vec3,
+  beta: Color,
+}
+
+struct SppmPixel {
+  ld: Color,                  // 相机路径直接看到的自发光之和
+  radius: f64,
+  n: f64,
+  tau: Color,
+  vp: Option<VisiblePoint>,
+}
+
+impl Sppm {
+  fn trace_visible_point(cam: &Camera, r: Ray, weight: f64, world: &dyn Hittable, pixel: &mut SppmPixel) {
+    // 沿镜面反射和折射追踪权重为 weight 的相机光线，直到遇到第一个漫反射表面。
+    let mut ray = r;
+    let mut beta = Color::new(weight, weight, weight);
+    pixel.vp = None;
+    if weight == 0.0 {
+      return;
+    }
+
+    for _ in 0..cam.max_depth {
+      let mut rec = HitRecord::default();
+      stats::count(|c| c.rays += 1);
+      if !world.hit(&ray, &Interval::new(0.001, rtweekend::INFINITY), &mut rec) {
+        pixel.ld += beta * cam.background;
+        return;
+      }
+      let Some(mat) = rec.mat.clone() else {
+        return;
+      };
+
+      pixel.ld += beta * mat.emitted(&ray, &rec, rec.u, rec.v, rec.p);
+
+      let mut srec = ScatterRecord::default();
+      if !mat.scatter(&ray, &rec, &mut srec) {
+        return;
+      }
+
+      if srec.skip_pdf {
+        beta = beta * srec.attenuation;
+        ray = srec.skip_pdf_ray;
+        continue;
+      }
+
+      pixel.vp = Some(VisiblePoint {
+        wo: -vec3::unit_vector(ray.direction()),
+        rec,
+        mat,
+        attenuation: srec.attenuation,
+        beta,
+      });
+      return;
+    }
+  }
+
+  fn trace_photon(&self, cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable, photons: &mut Vec<Photon>) {
+    let time = cam.sample_time();
+    let mut rec = HitRecord::default();
+    let pdf_pos = lights.random_on_surface(&mut rec, time);
+    if pdf_pos <= 0.0 {
+      return;
+    }
+    let Some(light_mat) = rec.mat.clone() else {
+      return;
+    };
+
+    // 在光源表面的法线半球内按余弦分布采样发射方向。
+    let uvw = Onb::new_from_w(rec.normal);
+    let direction = uvw.local_v(vec3::random_cosine_direction());
+    let cosine = vec3::dot(vec3::unit_vector(direction), rec.normal);
+    let pdf_dir = cosine / rtweekend::PI;
+    if pdf_dir <= 0.0 {
+      return;
+    }
+
+    let le = light_mat.emitted(&Ray::new(rec.p + direction, -direction), &rec, rec.u, rec.v, rec.p);
+    if le.near_zero() {
+      return;
+    }
+
+    let mut beta = le * cosine / (pdf_pos * pdf_dir);
+    let mut ray = Ray::new_with_time(rec.p, direction, time);
+
+    for _ in 0..cam.max_depth {
+      let mut rec = HitRecord::default();
+      stats::count(|c| c.rays += 1);
+      if !world.hit(&ray, &Interval::new(0.001, rtweekend::INFINITY), &mut rec) {
+        return;
+      }
+      let Some(mat) = rec.mat.clone() else {
+        return;
+      };
+
+      let mut srec = ScatterRecord::default();
+      if !mat.scatter(&ray, &rec, &mut srec) {
+        return;
+      }
+
+      if srec.skip_pdf {
+        beta = beta * srec.attenuation;
+        ray = srec.skip_pdf_ray;
+        continue;
+      }
+
+      photons.push(Photon {
+        p: rec.p,
+        wi: -vec3::unit_vector(ray.direction()),
+        power: beta,
+      });
+
+      let scattered = Ray::new_with_time(rec.p, srec.pdf.generate(), ray.time());
+      let pdf = srec.pdf.value(scattered.direction());
+      if pdf <= 0.0 {
+        return;
+      }
+      beta = beta * srec.attenuation * mat.scattering_pdf(&ray, &rec, &scattered) / pdf;
+      ray = scattered;
+    }
+  }
+
+  fn gather(vp: &VisiblePoint, photon: &Photon) -> Color {
+    // 返回光子在可见点处沿 wo 方向散射出的通量（不含可见点吞吐量）。
+    let cosine = vec3::dot(vp.rec.normal, photon.wi);
+    if cosine <= 0.0 {
+      return Color::default();
+    }
+    let r_in = Ray::new(vp.rec.p + vp.wo, -vp.wo);
+    let scattered = Ray::new(vp.rec.p, photon.wi);
+    let f = vp.attenuation * vp.mat.scattering_pdf(&r_in, &vp.rec, &scattered) / cosine;
+    f * photon.power
+  }
+}
+
+impl Integrator for Sppm {
+  fn render(&self, cam: &Camera, world: &dyn Hittable, lights: &dyn Hittable, film: &mut Framebuffer) {
+    let width = cam.film_width();
+    let height = cam.film_height();
+    let sqrt_spp = cam.sqrt_spp();
+    let iterations = cam.samples_per_pixel;
+
+    let mut pixels: Vec<SppmPixel> = (0..width * height).map(|_| SppmPixel {
+      ld: Color::default(),
+      radius: self.initial_radius,
+      n: 0.0,
+      tau: Color::default(),
+      vp: None,
+    }).collect();
+
+    progress::start("iterations", iterations, 0);
+    for iteration in 0..iterations {
+      progress::advance(iteration);
+      let s_i = (iteration % sqrt_spp) as i32;
+      let s_j = ((iteration / sqrt_spp) % sqrt_spp) as i32;
+
+      // 生成本次迭代的可见点。
+      for j in 0..height {
+        for i in 0..width {
+          let sample = cam.get_ray_sample(i as i32, j as i32, s_i, s_j);
+          Self::trace_visible_point(cam, sample.ray, sample.weight, world, &mut pixels[j * width + i]);
+        }
+      }
+
+      // 从光源发射光子并建立光子图。
+      let mut photons = Vec::with_capacity(self.photons_per_iteration);
+      for _ in 0..self.photons_per_iteration {
+        self.trace_photon(cam, world, lights, &mut photons);
+      }
+      let photon_map = PhotonMap::new(photons);
+      if photon_map.is_empty() {
+        continue;
+      }
+
+      // 在可见点处收集光子，并更新每个像素的半径和累积通量。
+      for pixel in pixels.iter_mut() {
+        let Some(vp) = &pixel.vp else {
+          continue;
+        };
+        let mut phi = Color::default();
+        let mut m = 0;
+        photon_map.for_each_in_radius(vp.rec.p, pixel.radius, |photon| {
+          phi += Self::gather(vp, photon);
+          m += 1;
+        });
+
+        if m > 0 {
+          let n_new = pixel.n + self.alpha * m as f64;
+          let radius_new = pixel.radius * (n_new / (pixel.n + m as f64)).sqrt();
+          pixel.tau = (pixel.tau + vp.beta * phi) * (radius_new * radius_new) / (pixel.radius * pixel.radius);
+          pixel.n = n_new;
+          pixel.radius = radius_new;
+        }
+      }
+    }
+    progress::advance(iterations);
+    progress::finish();
+
+    // film 保存样本总和，因此将最终的辐射亮度估计乘以 samples_per_pixel 写入。
+    let total_photons = (iterations * self.photons_per_iteration) as f64;
+    for j in 0..height {
+      for i in 0..width {
+        let pixel = &pixels[j * width + i];
+        let mut l = pixel.ld / iterations as f64;
+        l += pixel.tau / (total_photons * rtweekend::PI * pixel.radius * pixel.radius);
+        film.add(i, j, l * iterations as f64);
+      }
+    }
+  }
+}
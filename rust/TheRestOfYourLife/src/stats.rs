@@ -0,0 +1,131 @@
+use std::cell::RefCell;
+use std::io::Write;
+
+// 渲染统计计数器。每个线程各有一份，累加时只需借用线程局部变量，不需要同步。
+// 计数器只增不减，需要某段时间内的计数时，取前后两次快照之差。
+
+#[derive(Clone, Default)]
+pub struct Counters {
+  pub rays: u64,                   // Rays intersected with the whole scene, shadow rays included
+  pub camera_rays: u64,            // Rays generated by the camera, one per pixel sample
+  pub bounces: u64,                // Scattering events the path tracer continued a path from
+  pub shadow_rays: u64,            // Visibility rays between two points or of ambient occlusion
+  pub bvh_node_visits: u64,        // BVH nodes whose bounding box was tested
+  pub sphere_tests: u64,           // Ray intersection tests against spheres
+  pub quad_tests: u64,             // Ray intersection tests against quads
+  pub medium_tests: u64,           // Ray intersection tests against constant media
+  pub russian_roulette_kills: u64, // Paths the path tracer terminated with Russian roulette
+  pub clamped_samples: u64,        // Samples whose indirect light was clamped, each biasing the image darker
+  pub regularized_vertices: u64,   // Specular vertices roughened by path regularization, each biasing the image
+  pub path_lengths: Vec<u64>,      // Count of path tracer paths by bounces taken before terminating
+}
+
+thread_local! {
+  static COUNTERS: RefCell<Counters> = RefCell::new(Counters::default());
+}
+
+pub fn count<R, F: FnOnce(&mut Counters) -> R>(f: F) -> R {
+  // 例如 stats::count(|c| c.rays += 1)；读取单个计数器时用 stats::count(|c| c.rays)，不必复制整份快照。
+  COUNTERS.with(|c| f(&mut c.borrow_mut()))
+}
+
+pub fn counters() -> Counters {
+  // Returns a snapshot of this thread's counters.
+  COUNTERS.with(|c| c.borrow().clone())
+}
+
+impl Counters {
+  pub fn primitive_tests(&self) -> u64 {
+    self.sphere_tests + self.quad_tests + self.medium_tests
+  }
+
+  pub fn record_path(&mut self, bounces: usize) {
+    // 记录一条在 bounces 次反弹后终止的路径。
+    if self.path_lengths.len() <= bounces {
+      self.path_lengths.resize(bounces + 1, 0);
+    }
+    self.path_lengths[bounces] += 1;
+  }
+
+  fn totals(&self) -> [(&'static str, u64); 11] {
+    [
+      ("camera_rays", self.camera_rays),
+      ("rays", self.rays),
+      ("shadow_rays", self.shadow_rays),
+      ("bounces", self.bounces),
+      ("bvh_node_visits", self.bvh_node_visits),
+      ("sphere_tests", self.sphere_tests),
+      ("quad_tests", self.quad_tests),
+      ("medium_tests", self.medium_tests),
+      ("russian_roulette_kills", self.russian_roulette_kills),
+      ("clamped_samples", self.clamped_samples),
+      ("regularized_vertices", self.regularized_vertices),
+    ]
+  }
+
+  pub fn write_summary(&self, out: &mut dyn Write, seconds: f64) -> std::io::Result<()> {
+    // 以表格形式写出各计数器的总数和每秒的速率，以及路径长度的分布。
+    writeln!(out, "Render statistics over {:.3}s:", seconds)?;
+    for (name, n) in self.totals() {
+      writeln!(out, "  {:<24}{:>16}{:>16.0}/s", name, n, n as f64 / seconds.max(1e-9))?;
+    }
+
+    let rays = self.rays.max(1) as f64;
+    writeln!(out, "  {:<24}{:>16.2}", "bvh_nodes_per_ray", self.bvh_node_visits as f64 / rays)?;
+    writeln!(out, "  {:<24}{:>16.2}", "primitive_tests_per_ray", self.primitive_tests() as f64 / rays)?;
+
+    // 截断和正则化都以偏差换取更低的噪声，发生过时提醒用户图像不再是无偏的。
+    if self.clamped_samples > 0 || self.regularized_vertices > 0 {
+      writeln!(
+        out,
+        "  Note: the image is biased, {} samples were clamped and {} specular vertices regularized.",
+        self.clamped_samples, self.regularized_vertices,
+      )?;
+    }
+
+    let paths: u64 = self.path_lengths.iter().sum();
+    if paths > 0 {
+      writeln!(out, "  Path lengths (bounces: paths):")?;
+      for (bounces, &n) in self.path_lengths.iter().enumerate() {
+        writeln!(out, "    {:>3}: {:>12} {:>6.2}%", bounces, n, 100.0 * n as f64 / paths as f64)?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn write_json(&self, out: &mut dyn Write, seconds: f64) -> std::io::Result<()> {
+    write!(out, "{{\"seconds\":{:.3}", seconds)?;
+    for (name, n) in self.totals() {
+      write!(out, ",\"{}\":{}", name, n)?;
+    }
+    let path_lengths: Vec<String> = self.path_lengths.iter().map(u64::to_string).collect();
+    let biased = self.clamped_samples > 0 || self.regularized_vertices > 0;
+    writeln!(out, ",\"biased\":{},\"path_lengths\":[{}]}}", biased, path_lengths.join(","))
+  }
+}
+
+impl std::ops::Sub for &Counters {
+  type Output = Counters;
+
+  fn sub(self, earlier: &Counters) -> Counters {
+    let mut path_lengths = self.path_lengths.clone();
+    for (n, earlier) in path_lengths.iter_mut().zip(earlier.path_lengths.iter()) {
+      *n -= earlier;
+    }
+
+    Counters {
+      rays: self.rays - earlier.rays,
+      camera_rays: self.camera_rays - earlier.camera_rays,
+      bounces: self.bounces - earlier.bounces,
+      shadow_rays: self.shadow_rays - earlier.shadow_rays,
+      bvh_node_visits: self.bvh_node_visits - earlier.bvh_node_visits,
+      sphere_tests: self.sphere_tests - earlier.sphere_tests,
+      quad_tests: self.quad_tests - earlier.quad_tests,
+      medium_tests: self.medium_tests - earlier.medium_tests,
+      russian_roulette_kills: self.russian_roulette_kills - earlier.russian_roulette_kills,
+      clamped_samples: self.clamped_samples - earlier.clamped_samples,
+      regularized_vertices: self.regularized_vertices - earlier.regularized_vertices,
+      path_lengths,
+    }
+  }
+}
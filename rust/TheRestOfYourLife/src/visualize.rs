@@ -0,0 +1,158 @@
+use std::io::Write;
+
+use super::rtweekend;
+use super::color::Color;
+use super::camera::Camera;
+use super::hittable::{HitRecord, Hittable};
+use super::integrator::{self, Integrator};
+use super::framebuffer::Framebuffer;
+use super::interval::Interval;
+use super::onb::Onb;
+use super::ray::Ray;
+use super::vec3::{self, Vec3};
+use super::stats;
+
+// 用于诊断场景问题的调试视图，它们只查看相机光线的第一个交点，而不追踪完整的光传输路径。
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DebugView {
+  Normals,     // 世界空间法线，映射到 [0,1]
+  Uv,          // 表面纹理坐标 (u, v)
+  Depth,       // 击中距离，近处亮远处暗
+  FrontFace,   // 正面为绿色，背面为红色
+  MaterialId,  // 每种材质实例一种颜色
+  BvhHeatmap,  // 每条相机光线访问的 BVH 节点数
+}
+
+pub struct DebugIntegrator {
+  pub view: DebugView,
+  pub heatmap_max: f64, // Node visit count mapped to the hot end of the heatmap
+}
+
+impl DebugIntegrator {
+  pub fn new(view: DebugView) -> Self {
+    Self {
+      view,
+      heatmap_max: 64.0,
+    }
+  }
+
+  fn id_color(id: usize) -> Color {
+    // 用整数哈希为每个标识生成稳定的伪随机颜色。
+    let mut x = id as u64;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^= x >> 31;
+    Color::new(
+      (x & 0xff) as f64 / 255.0,
+      ((x >> 8) & 0xff) as f64 / 255.0,
+      ((x >> 16) & 0xff) as f64 / 255.0,
+    )
+  }
+
+  fn heatmap_color(t: f64) -> Color {
+    // 从蓝色经绿色到红色的色带。
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+      Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+      Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+    }
+  }
+
+  fn li(&self, r: &Ray, world: &dyn Hittable, scene_extent: f64) -> Color {
+    let visits_before = stats::count(|c| c.bvh_node_visits);
+    let mut rec = HitRecord::default();
+    stats::count(|c| c.rays += 1);
+    let hit = world.hit(r, &Interval::new(0.001, rtweekend::INFINITY), &mut rec);
+
+    if self.view == DebugView::BvhHeatmap {
+      let visits = stats::count(|c| c.bvh_node_visits) - visits_before;
+      return Self::heatmap_color(visits as f64 / self.heatmap_max);
+    }
+    if !hit {
+      return Color::default();
+    }
+
+    match self.view {
+      DebugView::Normals => 0.5 * (rec.normal + Color::new(1.0, 1.0, 1.0)),
+      DebugView::Uv => Color::new(rec.u, rec.v, 0.0),
+      DebugView::Depth => {
+        let distance = rec.t * r.direction().length();
+        let d = 1.0 - (distance / scene_extent).clamp(0.0, 1.0);
+        Color::new(d, d, d)
+      },
+      DebugView::FrontFace => {
+        if rec.front_face { Color::new(0.0, 1.0, 0.0) } else { Color::new(1.0, 0.0, 0.0) }
+      },
+      DebugView::MaterialId => Self::id_color(rec.material_id),
+      DebugView::BvhHeatmap => Color::default(),
+    }
+  }
+}
+
+impl Integrator for DebugIntegrator {
+  fn render(&self, cam: &Camera, world: &dyn Hittable, _lights: &dyn Hittable, film: &mut Framebuffer) {
+    // 深度视图以场景包围盒对角线长度作为最远距离。
+    let bbox = world.bounding_box();
+    let scene_extent = Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length();
+    let scene_extent = if scene_extent.is_finite() && scene_extent > 0.0 { scene_extent } else { 1.0 };
+
+    integrator::render_per_ray(cam, film, |r| self.li(r, world, scene_extent));
+  }
+}
+
+pub struct AmbientOcclusion {
+  pub radius: f64, // Occluders farther than this distance are ignored
+}
+
+impl AmbientOcclusion {
+  fn li(&self, r: &Ray, world: &dyn Hittable) -> Color {
+    let mut rec = HitRecord::default();
+    stats::count(|c| c.rays += 1);
+    if !world.hit(r, &Interval::new(0.001, rtweekend::INFINITY), &mut rec) {
+      return Color::new(1.0, 1.0, 1.0);
+    }
+
+    // 按余弦分布采样一条遮蔽光线，未被遮挡的比例即为环境光遮蔽值。
+    let uvw = Onb::new_from_w(rec.normal);
+    let direction = vec3::unit_vector(uvw.local_v(vec3::random_cosine_direction()));
+    let occlusion_ray = Ray::new_with_time(rec.p, direction, r.time());
+    let mut occluder = HitRecord::default();
+    stats::count(|c| {
+      c.rays += 1;
+      c.shadow_rays += 1;
+    });
+    if world.hit(&occlusion_ray, &Interval::new(0.001, self.radius), &mut occluder) {
+      Color::default()
+    } else {
+      Color::new(1.0, 1.0, 1.0)
+    }
+  }
+}
+
+impl Integrator for AmbientOcclusion {
+  fn render(&self, cam: &Camera, world: &dyn Hittable, _lights: &dyn Hittable, film: &mut Framebuffer) {
+    integrator::render_per_ray(cam, film, |r| self.li(r, world));
+  }
+}
+
+pub fn write_sample_heatmap(film: &Framebuffer, out: &mut dyn Write, samples_per_pixel: usize) -> std::io::Result<()> {
+  // 以 PPM 写出每个像素的样本数，最多样本的像素映射到色带最热的一端。
+  let mut max_count = 1;
+  for j in 0..film.height() {
+    for i in 0..film.width() {
+      max_count = max_count.max(film.sample_count(i, j, samples_per_pixel));
+    }
+  }
+
+  writeln!(out, "P3\n{} {}\n255", film.width(), film.height())?;
+  for j in 0..film.height() {
+    for i in 0..film.width() {
+      let t = film.sample_count(i, j, samples_per_pixel) as f64 / max_count as f64;
+      let c = DebugIntegrator::heatmap_color(t);
+      writeln!(out, "{} {} {}", (255.999 * c.x()) as i32, (255.999 * c.y()) as i32, (255.999 * c.z()) as i32)?;
+    }
+  }
+  Ok(())
+}